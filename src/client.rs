@@ -0,0 +1,674 @@
+//! Off-chain instruction builders (enabled with the `client` feature).
+//!
+//! Every builder returns a ready-to-sign `Instruction` whose account list follows the
+//! `# Accounts` order of the matching handler in `lib.rs`; PDAs come from `crate::pda`. SOL and SPL escrows are told
+//! apart by the escrow mint: SPL account lists use the associated token accounts of the
+//! vault PDA and of each party.
+
+use borsh::BorshDeserialize;
+use solana_program::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    program::MAX_RETURN_DATA,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+};
+
+use crate::instructions::{ConfigArgs, CreateOfferArgs, EscrowError, EscrowInstruction, InstructionVersion};
+use crate::pda;
+use crate::state::{EscrowAccount, EscrowInfo};
+use crate::utils::{AccountHelper, TokenTransfer, ASSOCIATED_TOKEN_PROGRAM_ID};
+
+/// Wire format used by the builders
+const VERSION: InstructionVersion = InstructionVersion::Borsh;
+
+/// Keys of an existing escrow needed to build its instructions
+#[derive(Clone, Debug, PartialEq)]
+pub struct EscrowKeys {
+    pub escrow: Pubkey,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub arbiter: Pubkey,
+    /// Native mint for SOL escrows
+    pub mint: Pubkey,
+    /// SPL Token or Token-2022 program of the mint (ignored for SOL escrows)
+    pub token_program: Pubkey,
+    pub rent_payer: Pubkey,
+    /// Fee collector, only set when the escrow charges a settlement fee
+    pub settlement_fee_collector: Option<Pubkey>,
+    /// The offer has a seller bond: the seller creates the vault token account and may
+    /// need to be paid on cancellation
+    pub with_seller_bond: bool,
+}
+
+impl EscrowKeys {
+    /// Collect the keys from a decoded escrow account
+    pub fn from_escrow(escrow: Pubkey, data: &EscrowAccount, token_program: Pubkey) -> Self {
+        Self {
+            escrow,
+            buyer: data.buyer,
+            seller: data.seller,
+            arbiter: data.arbiter,
+            mint: data.mint,
+            token_program,
+            rent_payer: data.rent_payer,
+            settlement_fee_collector: (data.settlement_fee_bps > 0).then_some(data.fee_collector),
+            with_seller_bond: data.seller_bond > 0,
+        }
+    }
+
+    pub fn is_spl(&self) -> bool {
+        !TokenTransfer::is_native_mint(&self.mint)
+    }
+
+    pub fn vault(&self, program_id: &Pubkey) -> Pubkey {
+        pda::find_vault_address(program_id, &self.escrow).0
+    }
+
+    pub fn milestones(&self, program_id: &Pubkey) -> Pubkey {
+        pda::find_milestone_address(program_id, &self.escrow).0
+    }
+
+    /// Associated token account of `owner` for the escrow mint
+    pub fn token_account(&self, owner: &Pubkey) -> Pubkey {
+        AccountHelper::associated_token_address(owner, &self.mint, &self.token_program)
+    }
+
+    /// Mint, vault token account, the given parties' token accounts and the token program
+    fn spl_accounts(&self, program_id: &Pubkey, parties: &[Pubkey]) -> Vec<AccountMeta> {
+        let mut accounts = vec![
+            AccountMeta::new_readonly(self.mint, false),
+            AccountMeta::new(self.token_account(&self.vault(program_id)), false),
+        ];
+        accounts.extend(parties.iter().map(|party| AccountMeta::new(self.token_account(party), false)));
+        accounts.push(AccountMeta::new_readonly(self.token_program, false));
+        accounts
+    }
+
+    /// Settlement fee destination, passed as the last account when a fee is due
+    fn fee_destination(&self) -> Option<AccountMeta> {
+        self.settlement_fee_collector.map(|collector| {
+            let destination = if self.is_spl() { self.token_account(&collector) } else { collector };
+            AccountMeta::new(destination, false)
+        })
+    }
+}
+
+fn instruction(program_id: &Pubkey, instruction: EscrowInstruction, accounts: Vec<AccountMeta>) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: instruction.pack(VERSION),
+    }
+}
+
+/// Create an offer. `fee_collector` must be the collector of the ProgramConfig.
+pub fn create_offer_ix(
+    program_id: &Pubkey,
+    initiator: &Pubkey,
+    fee_collector: &Pubkey,
+    args: CreateOfferArgs,
+) -> Instruction {
+    let (escrow, _) = pda::find_escrow_address(program_id, &args.seed);
+    let accounts = vec![
+        AccountMeta::new(*initiator, true),
+        AccountMeta::new(escrow, false),
+        AccountMeta::new(pda::find_vault_address(program_id, &escrow).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(args.mint, false),
+        AccountMeta::new(*fee_collector, false),
+        AccountMeta::new_readonly(pda::find_config_address(program_id).0, false),
+    ];
+    instruction(program_id, EscrowInstruction::CreateOffer(args), accounts)
+}
+
+/// Create an offer with the service fee and rent paid by `payer`, which becomes the escrow's rent payer
+pub fn create_offer_with_payer_ix(
+    program_id: &Pubkey,
+    initiator: &Pubkey,
+    payer: &Pubkey,
+    fee_collector: &Pubkey,
+    args: CreateOfferArgs,
+) -> Instruction {
+    let mut ix = create_offer_ix(program_id, initiator, fee_collector, args);
+    ix.accounts[0].is_writable = false;
+    ix.accounts.push(AccountMeta::new(*payer, true));
+    ix
+}
+
+/// Join an offer. Pass the escrow's fee collector when it holds a refundable service fee.
+pub fn join_offer_ix(
+    program_id: &Pubkey,
+    escrow: &Pubkey,
+    joiner: &Pubkey,
+    role: u8,
+    arbiter_opt_in: bool,
+    held_fee_collector: Option<&Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*joiner, true),
+        AccountMeta::new(*escrow, false),
+    ];
+    if let Some(fee_collector) = held_fee_collector {
+        accounts.push(AccountMeta::new(pda::find_vault_address(program_id, escrow).0, false));
+        accounts.push(AccountMeta::new(*fee_collector, false));
+    }
+    let data = EscrowInstruction::JoinOffer { role, joiner: *joiner, arbiter_opt_in };
+    instruction(program_id, data, accounts)
+}
+
+pub fn fund_escrow_ix(program_id: &Pubkey, keys: &EscrowKeys) -> Instruction {
+    instruction(program_id, EscrowInstruction::FundEscrow, deposit_accounts(program_id, keys, &keys.buyer))
+}
+
+/// Seller deposits the offer's seller bond, before the buyer funds
+pub fn fund_collateral_ix(program_id: &Pubkey, keys: &EscrowKeys) -> Instruction {
+    instruction(program_id, EscrowInstruction::FundCollateral, deposit_accounts(program_id, keys, &keys.seller))
+}
+
+/// Accounts of FundEscrow and FundCollateral
+fn deposit_accounts(program_id: &Pubkey, keys: &EscrowKeys, depositor: &Pubkey) -> Vec<AccountMeta> {
+    let vault = keys.vault(program_id);
+    let mut accounts = vec![
+        AccountMeta::new(*depositor, true),
+        AccountMeta::new(keys.escrow, false),
+        AccountMeta::new(vault, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if keys.is_spl() {
+        accounts.extend([
+            AccountMeta::new_readonly(keys.mint, false),
+            AccountMeta::new(keys.token_account(depositor), false),
+            AccountMeta::new(keys.token_account(&vault), false),
+            AccountMeta::new_readonly(keys.token_program, false),
+            AccountMeta::new_readonly(ASSOCIATED_TOKEN_PROGRAM_ID, false),
+        ]);
+    }
+    accounts
+}
+
+pub fn seller_confirm_ix(program_id: &Pubkey, keys: &EscrowKeys) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new_readonly(keys.seller, true),
+        AccountMeta::new(keys.escrow, false),
+    ];
+    instruction(program_id, EscrowInstruction::SellerConfirm, accounts)
+}
+
+pub fn confirm_escrow_ix(program_id: &Pubkey, keys: &EscrowKeys) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(keys.buyer, true),
+        AccountMeta::new(keys.escrow, false),
+        AccountMeta::new(keys.vault(program_id), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new(keys.seller, false),
+    ];
+    if keys.is_spl() {
+        accounts.extend(keys.spl_accounts(program_id, &[keys.seller]));
+    }
+    accounts.extend(keys.fee_destination());
+    instruction(program_id, EscrowInstruction::ConfirmEscrow, accounts)
+}
+
+pub fn arbiter_confirm_ix(program_id: &Pubkey, keys: &EscrowKeys) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(keys.arbiter, true),
+        AccountMeta::new(keys.escrow, false),
+        AccountMeta::new(keys.vault(program_id), false),
+        AccountMeta::new(keys.seller, false),
+    ];
+    if keys.is_spl() {
+        accounts.extend(keys.spl_accounts(program_id, &[keys.seller]));
+        accounts.push(AccountMeta::new(keys.token_account(&keys.arbiter), false));
+    }
+    accounts.extend(keys.fee_destination());
+    instruction(program_id, EscrowInstruction::ArbiterConfirm, accounts)
+}
+
+pub fn arbiter_cancel_ix(program_id: &Pubkey, keys: &EscrowKeys) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(keys.arbiter, true),
+        AccountMeta::new(keys.escrow, false),
+        AccountMeta::new(keys.vault(program_id), false),
+        AccountMeta::new(keys.buyer, false),
+    ];
+    if keys.is_spl() {
+        accounts.extend(keys.spl_accounts(program_id, &[keys.buyer]));
+        accounts.push(AccountMeta::new(keys.token_account(&keys.arbiter), false));
+    }
+    instruction(program_id, EscrowInstruction::ArbiterCancel, accounts)
+}
+
+/// Split the vault. `mode` 0 = `seller_share` in basis points, 1 = explicit seller amount.
+pub fn arbiter_settle_ix(program_id: &Pubkey, keys: &EscrowKeys, mode: u8, seller_share: u64) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(keys.arbiter, true),
+        AccountMeta::new(keys.escrow, false),
+        AccountMeta::new(keys.vault(program_id), false),
+        AccountMeta::new(keys.buyer, false),
+        AccountMeta::new(keys.seller, false),
+    ];
+    if keys.is_spl() {
+        accounts.extend(keys.spl_accounts(program_id, &[keys.buyer, keys.seller]));
+        accounts.push(AccountMeta::new(keys.token_account(&keys.arbiter), false));
+    }
+    accounts.extend(keys.fee_destination());
+    instruction(program_id, EscrowInstruction::ArbiterSettle { mode, seller_share }, accounts)
+}
+
+pub fn raise_dispute_ix(program_id: &Pubkey, escrow: &Pubkey, disputant: &Pubkey) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new_readonly(*disputant, true),
+        AccountMeta::new(*escrow, false),
+    ];
+    instruction(program_id, EscrowInstruction::RaiseDispute, accounts)
+}
+
+pub fn mutual_cancel_ix(program_id: &Pubkey, keys: &EscrowKeys) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(keys.buyer, true),
+        AccountMeta::new(keys.seller, true),
+        AccountMeta::new(keys.escrow, false),
+        AccountMeta::new(keys.vault(program_id), false),
+    ];
+    if keys.is_spl() {
+        accounts.extend(keys.spl_accounts(program_id, &[keys.buyer]));
+        if keys.with_seller_bond {
+            accounts.push(AccountMeta::new(keys.token_account(&keys.seller), false));
+        }
+    }
+    instruction(program_id, EscrowInstruction::MutualCancel, accounts)
+}
+
+/// Permissionless refund once the delivery (or funding) deadline has passed
+pub fn claim_expired_refund_ix(program_id: &Pubkey, keys: &EscrowKeys) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(keys.escrow, false),
+        AccountMeta::new(keys.vault(program_id), false),
+        AccountMeta::new(keys.buyer, false),
+        AccountMeta::new(keys.seller, false),
+        AccountMeta::new_readonly(keys.milestones(program_id), false),
+    ];
+    if keys.is_spl() {
+        accounts.extend(keys.spl_accounts(program_id, &[keys.buyer]));
+        // An unfunded offer returns the seller bond
+        if keys.with_seller_bond {
+            accounts.push(AccountMeta::new(keys.token_account(&keys.seller), false));
+        }
+    }
+    instruction(program_id, EscrowInstruction::ClaimExpiredRefund, accounts)
+}
+
+/// Permissionless release to the seller once the dispute window has elapsed
+pub fn claim_auto_release_ix(program_id: &Pubkey, keys: &EscrowKeys) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(keys.escrow, false),
+        AccountMeta::new(keys.vault(program_id), false),
+        AccountMeta::new(keys.seller, false),
+    ];
+    if keys.is_spl() {
+        accounts.extend(keys.spl_accounts(program_id, &[keys.seller]));
+    }
+    accounts.extend(keys.fee_destination());
+    instruction(program_id, EscrowInstruction::ClaimAutoRelease, accounts)
+}
+
+pub fn init_milestones_ix(
+    program_id: &Pubkey,
+    escrow: &Pubkey,
+    initiator: &Pubkey,
+    amounts: Vec<u64>,
+) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new(*initiator, true),
+        AccountMeta::new(*escrow, false),
+        AccountMeta::new(pda::find_milestone_address(program_id, escrow).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    instruction(program_id, EscrowInstruction::InitMilestones { amounts }, accounts)
+}
+
+pub fn confirm_milestone_ix(program_id: &Pubkey, keys: &EscrowKeys, index: u8) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new_readonly(keys.seller, true),
+        AccountMeta::new_readonly(keys.escrow, false),
+        AccountMeta::new(keys.milestones(program_id), false),
+    ];
+    instruction(program_id, EscrowInstruction::ConfirmMilestone { index }, accounts)
+}
+
+pub fn release_milestone_ix(program_id: &Pubkey, keys: &EscrowKeys, index: u8) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(keys.buyer, true),
+        AccountMeta::new(keys.escrow, false),
+        AccountMeta::new(keys.vault(program_id), false),
+        AccountMeta::new(keys.milestones(program_id), false),
+        AccountMeta::new(keys.seller, false),
+    ];
+    if keys.is_spl() {
+        accounts.extend(keys.spl_accounts(program_id, &[keys.seller]));
+    }
+    accounts.extend(keys.fee_destination());
+    instruction(program_id, EscrowInstruction::ReleaseMilestone { index }, accounts)
+}
+
+/// Rule on one milestone. `ruling` 0 = release to seller, 1 = refund to buyer.
+pub fn arbiter_rule_milestone_ix(program_id: &Pubkey, keys: &EscrowKeys, index: u8, ruling: u8) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(keys.arbiter, true),
+        AccountMeta::new(keys.escrow, false),
+        AccountMeta::new(keys.vault(program_id), false),
+        AccountMeta::new(keys.milestones(program_id), false),
+        AccountMeta::new(keys.buyer, false),
+        AccountMeta::new(keys.seller, false),
+    ];
+    if keys.is_spl() {
+        accounts.extend(keys.spl_accounts(program_id, &[keys.buyer, keys.seller]));
+        accounts.push(AccountMeta::new(keys.token_account(&keys.arbiter), false));
+    }
+    accounts.extend(keys.fee_destination());
+    instruction(program_id, EscrowInstruction::ArbiterRuleMilestone { index, ruling }, accounts)
+}
+
+/// Permissionless close of a completed or cancelled escrow, refunding the rent payer
+pub fn close_escrow_ix(program_id: &Pubkey, keys: &EscrowKeys) -> Instruction {
+    let vault = keys.vault(program_id);
+    let mut accounts = vec![
+        AccountMeta::new(keys.rent_payer, false),
+        AccountMeta::new(keys.escrow, false),
+        AccountMeta::new(vault, false),
+        AccountMeta::new(keys.milestones(program_id), false),
+    ];
+    if keys.is_spl() {
+        accounts.extend([
+            AccountMeta::new(keys.token_account(&vault), false),
+            AccountMeta::new_readonly(keys.token_program, false),
+        ]);
+    }
+    instruction(program_id, EscrowInstruction::CloseEscrow, accounts)
+}
+
+/// `rent_payer` receives the refunds; the initiator itself unless a separate payer created the offer
+pub fn cancel_offer_ix(program_id: &Pubkey, escrow: &Pubkey, initiator: &Pubkey, rent_payer: &Pubkey) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new(*initiator, true),
+        AccountMeta::new(*escrow, false),
+        AccountMeta::new(pda::find_vault_address(program_id, escrow).0, false),
+        AccountMeta::new(pda::find_milestone_address(program_id, escrow).0, false),
+        AccountMeta::new(*rent_payer, false),
+    ];
+    instruction(program_id, EscrowInstruction::CancelOffer, accounts)
+}
+
+pub fn migrate_escrow_ix(program_id: &Pubkey, escrow: &Pubkey, payer: &Pubkey) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(*escrow, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    instruction(program_id, EscrowInstruction::MigrateEscrow, accounts)
+}
+
+/// Simulate it and pass the return data to [`decode_escrow_info`]
+pub fn get_escrow_info_ix(program_id: &Pubkey, keys: &EscrowKeys) -> Instruction {
+    let vault = keys.vault(program_id);
+    let mut accounts = vec![
+        AccountMeta::new_readonly(keys.escrow, false),
+        AccountMeta::new_readonly(vault, false),
+    ];
+    if keys.is_spl() {
+        accounts.push(AccountMeta::new_readonly(keys.token_account(&vault), false));
+    }
+    instruction(program_id, EscrowInstruction::GetEscrowInfo, accounts)
+}
+
+/// Decode the return data of a GetEscrowInfo simulation.
+/// `None` if the data was returned by another program or is not an `EscrowInfo`.
+/// Data with its trailing zero bytes stripped is accepted.
+pub fn decode_escrow_info(program_id: &Pubkey, return_program_id: &Pubkey, data: &[u8]) -> Option<EscrowInfo> {
+    if return_program_id != program_id || data.len() > MAX_RETURN_DATA {
+        return None;
+    }
+    let mut padded = data.to_vec();
+    padded.resize(MAX_RETURN_DATA, 0);
+    let mut rest = &padded[..];
+    let info = EscrowInfo::deserialize(&mut rest).ok()?;
+    // Only zero padding may follow the info
+    (MAX_RETURN_DATA - rest.len() >= data.len()).then_some(info)
+}
+
+/// Program error behind a failed instruction, `None` for errors not raised by this program
+pub fn decode_error(error: &ProgramError) -> Option<EscrowError> {
+    match error {
+        ProgramError::Custom(code) => EscrowError::from_code(*code),
+        _ => None,
+    }
+}
+
+/// Program error behind a failed transaction instruction (as reported by RPC or simulation)
+pub fn decode_instruction_error(error: &InstructionError) -> Option<EscrowError> {
+    match error {
+        InstructionError::Custom(code) => EscrowError::from_code(*code),
+        _ => None,
+    }
+}
+
+/// Create the ProgramConfig. `authority` must be the program upgrade authority.
+pub fn initialize_config_ix(program_id: &Pubkey, authority: &Pubkey, config: ConfigArgs) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(pda::find_config_address(program_id).0, false),
+        AccountMeta::new_readonly(pda::find_program_data_address(program_id), false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    instruction(program_id, EscrowInstruction::InitializeConfig(config), accounts)
+}
+
+pub fn update_config_ix(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    new_admin: &Pubkey,
+    config: ConfigArgs,
+) -> Instruction {
+    let accounts = vec![
+        AccountMeta::new_readonly(*admin, true),
+        AccountMeta::new(pda::find_config_address(program_id).0, false),
+    ];
+    instruction(program_id, EscrowInstruction::UpdateConfig { new_admin: *new_admin, config }, accounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{NATIVE_MINT, TOKEN_2022_PROGRAM_ID};
+
+    fn keys(mint: Pubkey, settlement_fee_collector: Option<Pubkey>) -> EscrowKeys {
+        let buyer = Pubkey::new_unique();
+        EscrowKeys {
+            escrow: Pubkey::new_unique(),
+            buyer,
+            seller: Pubkey::new_unique(),
+            arbiter: Pubkey::new_unique(),
+            mint,
+            token_program: TOKEN_2022_PROGRAM_ID,
+            rent_payer: buyer,
+            settlement_fee_collector,
+            with_seller_bond: false,
+        }
+    }
+
+    fn account_keys(ix: &Instruction) -> Vec<Pubkey> {
+        ix.accounts.iter().map(|meta| meta.pubkey).collect()
+    }
+
+    #[test]
+    fn test_create_offer_derives_pdas() {
+        let program_id = Pubkey::new_unique();
+        let initiator = Pubkey::new_unique();
+        let fee_collector = Pubkey::new_unique();
+        let args = CreateOfferArgs {
+            role: 0,
+            amount: 1_000,
+            arbiter: Pubkey::new_unique(),
+            mint: NATIVE_MINT,
+            seed: [3u8; 32],
+            funding_window: 0,
+            delivery_window: 0,
+            dispute_window: 0,
+            arbiter_opt_in: false,
+            arbiter_fee_mode: 0,
+            arbiter_fee: 0,
+            seller_bond: 0,
+        };
+        let ix = create_offer_ix(&program_id, &initiator, &fee_collector, args.clone());
+
+        let (escrow, _) = pda::find_escrow_address(&program_id, &[3u8; 32]);
+        let (vault, _) = pda::find_vault_address(&program_id, &escrow);
+        let (config, _) = pda::find_config_address(&program_id);
+        assert_eq!(
+            account_keys(&ix),
+            vec![initiator, escrow, vault, system_program::id(), NATIVE_MINT, fee_collector, config]
+        );
+        assert!(ix.accounts[0].is_signer && ix.accounts[0].is_writable);
+        assert_eq!(EscrowInstruction::unpack(&ix.data).unwrap(), EscrowInstruction::CreateOffer(args.clone()));
+
+        let payer = Pubkey::new_unique();
+        let ix = create_offer_with_payer_ix(&program_id, &initiator, &payer, &fee_collector, args);
+        assert_eq!(ix.accounts.len(), 8);
+        assert!(ix.accounts[0].is_signer && !ix.accounts[0].is_writable);
+        assert_eq!(ix.accounts[7], AccountMeta::new(payer, true));
+    }
+
+    #[test]
+    fn test_sol_and_spl_account_lists() {
+        let program_id = Pubkey::new_unique();
+
+        let sol = keys(NATIVE_MINT, None);
+        let ix = fund_escrow_ix(&program_id, &sol);
+        assert_eq!(ix.accounts.len(), 4);
+
+        let mint = Pubkey::new_unique();
+        let spl = keys(mint, None);
+        let ix = fund_escrow_ix(&program_id, &spl);
+        assert_eq!(
+            account_keys(&ix)[4..],
+            [
+                mint,
+                AccountHelper::associated_token_address(&spl.buyer, &mint, &TOKEN_2022_PROGRAM_ID),
+                pda::find_vault_token_address(&program_id, &spl.escrow, &mint, &TOKEN_2022_PROGRAM_ID),
+                TOKEN_2022_PROGRAM_ID,
+                ASSOCIATED_TOKEN_PROGRAM_ID,
+            ]
+        );
+
+        let ix = arbiter_settle_ix(&program_id, &spl, 0, 5_000);
+        assert_eq!(ix.accounts.len(), 11);
+        assert_eq!(ix.accounts[10].pubkey, spl.token_account(&spl.arbiter));
+        assert_eq!(
+            EscrowInstruction::unpack(&ix.data).unwrap(),
+            EscrowInstruction::ArbiterSettle { mode: 0, seller_share: 5_000 }
+        );
+    }
+
+    #[test]
+    fn test_settlement_fee_destination_is_last() {
+        let program_id = Pubkey::new_unique();
+        let collector = Pubkey::new_unique();
+
+        let sol = keys(NATIVE_MINT, Some(collector));
+        let ix = confirm_escrow_ix(&program_id, &sol);
+        assert_eq!(ix.accounts.last().unwrap().pubkey, collector);
+        assert!(arbiter_cancel_ix(&program_id, &sol).accounts.iter().all(|meta| meta.pubkey != collector));
+
+        let spl = keys(Pubkey::new_unique(), Some(collector));
+        let ix = arbiter_confirm_ix(&program_id, &spl);
+        assert_eq!(ix.accounts.len(), 10);
+        assert_eq!(ix.accounts.last().unwrap().pubkey, spl.token_account(&collector));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(decode_error(&EscrowError::NotSeller.into()), Some(EscrowError::NotSeller));
+        assert_eq!(decode_error(&ProgramError::Custom(7)), None);
+        assert_eq!(decode_error(&ProgramError::MissingRequiredSignature), None);
+
+        assert_eq!(
+            decode_instruction_error(&InstructionError::Custom(EscrowError::VaultNotEmpty as u32)),
+            Some(EscrowError::VaultNotEmpty)
+        );
+        assert_eq!(decode_instruction_error(&InstructionError::InvalidSeeds), None);
+    }
+
+    #[test]
+    fn test_keys_from_escrow() {
+        let mut data = EscrowAccount::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            100,
+            255,
+            &NATIVE_MINT,
+            &Pubkey::new_unique(),
+        );
+        let escrow = Pubkey::new_unique();
+
+        let keys = EscrowKeys::from_escrow(escrow, &data, TOKEN_2022_PROGRAM_ID);
+        assert_eq!(keys.settlement_fee_collector, None);
+        assert_eq!(keys.rent_payer, data.buyer);
+
+        data.settlement_fee_bps = 100;
+        let keys = EscrowKeys::from_escrow(escrow, &data, TOKEN_2022_PROGRAM_ID);
+        assert_eq!(keys.settlement_fee_collector, Some(data.fee_collector));
+        assert!(!keys.with_seller_bond);
+
+        data.seller_bond = 1;
+        assert!(EscrowKeys::from_escrow(escrow, &data, TOKEN_2022_PROGRAM_ID).with_seller_bond);
+    }
+
+    #[test]
+    fn test_seller_bond_accounts() {
+        let program_id = Pubkey::new_unique();
+        let spl = EscrowKeys { with_seller_bond: true, ..keys(Pubkey::new_unique(), None) };
+        let seller_token_account = spl.token_account(&spl.seller);
+
+        let ix = fund_collateral_ix(&program_id, &spl);
+        assert_eq!(ix.accounts[0], AccountMeta::new(spl.seller, true));
+        assert_eq!(ix.accounts[5].pubkey, seller_token_account);
+        assert_eq!(EscrowInstruction::unpack(&ix.data).unwrap(), EscrowInstruction::FundCollateral);
+
+        // The seller's token account follows the token program, where the handlers look for it
+        let ix = mutual_cancel_ix(&program_id, &spl);
+        assert!(ix.accounts[1].is_writable);
+        assert_eq!(ix.accounts.last().unwrap().pubkey, seller_token_account);
+        let ix = claim_expired_refund_ix(&program_id, &spl);
+        assert_eq!(ix.accounts[3], AccountMeta::new(spl.seller, false));
+        assert_eq!(account_keys(&ix)[9..], [seller_token_account]);
+        let sol = EscrowKeys { with_seller_bond: true, ..keys(NATIVE_MINT, None) };
+        assert_eq!(account_keys(&claim_expired_refund_ix(&program_id, &sol))[3..], [sol.seller, sol.milestones(&program_id)]);
+    }
+
+    #[test]
+    fn test_decode_escrow_info() {
+        let program_id = Pubkey::new_unique();
+        let escrow = EscrowAccount::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            100,
+            255,
+            &NATIVE_MINT,
+            &Pubkey::new_unique(),
+        );
+        let info = EscrowInfo::new(escrow, 2, 1_700_000_000, Some(0)).unwrap();
+        let data = borsh::to_vec(&info).unwrap();
+        assert_eq!(*data.last().unwrap(), 0);
+
+        assert_eq!(decode_escrow_info(&program_id, &program_id, &data), Some(info.clone()));
+        let trimmed = &data[..=data.iter().rposition(|&byte| byte != 0).unwrap()];
+        assert_eq!(decode_escrow_info(&program_id, &program_id, trimmed), Some(info));
+
+        assert_eq!(decode_escrow_info(&program_id, &Pubkey::new_unique(), &data), None);
+        assert_eq!(decode_escrow_info(&program_id, &program_id, &[data.as_slice(), &[1]].concat()), None);
+        let mut invalid = data.clone();
+        invalid[EscrowAccount::BODY_LEN + 1 + 8] = 2; // `vault_balance` option tag
+        assert_eq!(decode_escrow_info(&program_id, &program_id, &invalid), None);
+    }
+}
@@ -0,0 +1,169 @@
+//! Invoke the escrow program from another on-chain program (enabled with the `cpi` feature,
+//! which also turns off this crate's entrypoint).
+//!
+//! [`EscrowCpi`] builds each instruction with the matching `client` builder and calls
+//! `invoke_signed`, so any participant may be a PDA of the calling program signing with its
+//! seeds. Participants that pay SOL (the initiator of an offer, the buyer funding a SOL escrow,
+//! the seller depositing a SOL bond, or whoever creates the vault token account) are debited
+//! through the system program and must be system-owned accounts without data. An initiator
+//! that holds data can leave the service fee and rent to a separate payer.
+//!
+//! ```ignore
+//! let cpi = EscrowCpi::new(escrow_program, remaining_accounts)
+//!     .with_signer(&[&[b"authority", &[authority_bump]]]);
+//! let keys = cpi.escrow_keys(escrow, spl_token::id())?;
+//! cpi.fund_escrow(&keys)?;
+//! ```
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::Instruction,
+    msg,
+    program::{get_return_data, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::client::{self, EscrowKeys};
+use crate::instructions::{CreateOfferArgs, EscrowError};
+use crate::state::{EscrowAccount, EscrowInfo};
+
+/// The escrow program, the accounts its instructions need and the seeds of the caller's signing PDAs
+pub struct EscrowCpi<'a, 'info> {
+    pub program: &'a AccountInfo<'info>,
+    /// Every account the invoked instruction lists, in any order
+    pub accounts: &'a [AccountInfo<'info>],
+    pub signer_seeds: &'a [&'a [&'a [u8]]],
+}
+
+impl<'a, 'info> EscrowCpi<'a, 'info> {
+    pub fn new(program: &'a AccountInfo<'info>, accounts: &'a [AccountInfo<'info>]) -> Self {
+        Self { program, accounts, signer_seeds: &[] }
+    }
+
+    /// Sign for PDAs of the calling program
+    pub fn with_signer(self, signer_seeds: &'a [&'a [&'a [u8]]]) -> Self {
+        Self { signer_seeds, ..self }
+    }
+
+    /// Keys of an escrow owned by the escrow program, for the instructions that take them
+    pub fn escrow_keys(&self, escrow: &AccountInfo, token_program: Pubkey) -> Result<EscrowKeys, ProgramError> {
+        if escrow.owner != self.program.key {
+            msg!("{} is not an escrow of {}", escrow.key, self.program.key);
+            return Err(EscrowError::InvalidAccountOwner.into());
+        }
+        let data = EscrowAccount::from_account_data(&escrow.try_borrow_data()?)?;
+        Ok(EscrowKeys::from_escrow(*escrow.key, &data, token_program))
+    }
+
+    fn invoke(&self, instruction: Instruction) -> ProgramResult {
+        let mut account_infos = Vec::with_capacity(self.accounts.len() + 1);
+        account_infos.push(self.program.clone());
+        account_infos.extend_from_slice(self.accounts);
+        invoke_signed(&instruction, &account_infos, self.signer_seeds)
+    }
+
+    /// `payer`, when set, pays the service fee and rent and becomes the escrow's rent payer
+    pub fn create_offer(
+        &self,
+        initiator: &Pubkey,
+        payer: Option<&Pubkey>,
+        fee_collector: &Pubkey,
+        args: CreateOfferArgs,
+    ) -> ProgramResult {
+        let program_id = self.program.key;
+        self.invoke(match payer {
+            Some(payer) => client::create_offer_with_payer_ix(program_id, initiator, payer, fee_collector, args),
+            None => client::create_offer_ix(program_id, initiator, fee_collector, args),
+        })
+    }
+
+    pub fn join_offer(
+        &self,
+        escrow: &Pubkey,
+        joiner: &Pubkey,
+        role: u8,
+        arbiter_opt_in: bool,
+        held_fee_collector: Option<&Pubkey>,
+    ) -> ProgramResult {
+        self.invoke(client::join_offer_ix(self.program.key, escrow, joiner, role, arbiter_opt_in, held_fee_collector))
+    }
+
+    pub fn fund_escrow(&self, keys: &EscrowKeys) -> ProgramResult {
+        self.invoke(client::fund_escrow_ix(self.program.key, keys))
+    }
+
+    /// Seller deposits its bond, before the buyer may fund
+    pub fn fund_collateral(&self, keys: &EscrowKeys) -> ProgramResult {
+        self.invoke(client::fund_collateral_ix(self.program.key, keys))
+    }
+
+    pub fn seller_confirm(&self, keys: &EscrowKeys) -> ProgramResult {
+        self.invoke(client::seller_confirm_ix(self.program.key, keys))
+    }
+
+    /// Buyer releases the vault to the seller
+    pub fn confirm_escrow(&self, keys: &EscrowKeys) -> ProgramResult {
+        self.invoke(client::confirm_escrow_ix(self.program.key, keys))
+    }
+
+    pub fn raise_dispute(&self, escrow: &Pubkey, disputant: &Pubkey) -> ProgramResult {
+        self.invoke(client::raise_dispute_ix(self.program.key, escrow, disputant))
+    }
+
+    pub fn mutual_cancel(&self, keys: &EscrowKeys) -> ProgramResult {
+        self.invoke(client::mutual_cancel_ix(self.program.key, keys))
+    }
+
+    pub fn arbiter_confirm(&self, keys: &EscrowKeys) -> ProgramResult {
+        self.invoke(client::arbiter_confirm_ix(self.program.key, keys))
+    }
+
+    pub fn arbiter_cancel(&self, keys: &EscrowKeys) -> ProgramResult {
+        self.invoke(client::arbiter_cancel_ix(self.program.key, keys))
+    }
+
+    pub fn arbiter_settle(&self, keys: &EscrowKeys, mode: u8, seller_share: u64) -> ProgramResult {
+        self.invoke(client::arbiter_settle_ix(self.program.key, keys, mode, seller_share))
+    }
+
+    pub fn claim_expired_refund(&self, keys: &EscrowKeys) -> ProgramResult {
+        self.invoke(client::claim_expired_refund_ix(self.program.key, keys))
+    }
+
+    pub fn claim_auto_release(&self, keys: &EscrowKeys) -> ProgramResult {
+        self.invoke(client::claim_auto_release_ix(self.program.key, keys))
+    }
+
+    pub fn init_milestones(&self, escrow: &Pubkey, initiator: &Pubkey, amounts: Vec<u64>) -> ProgramResult {
+        self.invoke(client::init_milestones_ix(self.program.key, escrow, initiator, amounts))
+    }
+
+    pub fn confirm_milestone(&self, keys: &EscrowKeys, index: u8) -> ProgramResult {
+        self.invoke(client::confirm_milestone_ix(self.program.key, keys, index))
+    }
+
+    pub fn release_milestone(&self, keys: &EscrowKeys, index: u8) -> ProgramResult {
+        self.invoke(client::release_milestone_ix(self.program.key, keys, index))
+    }
+
+    pub fn arbiter_rule_milestone(&self, keys: &EscrowKeys, index: u8, ruling: u8) -> ProgramResult {
+        self.invoke(client::arbiter_rule_milestone_ix(self.program.key, keys, index, ruling))
+    }
+
+    pub fn cancel_offer(&self, escrow: &Pubkey, initiator: &Pubkey, rent_payer: &Pubkey) -> ProgramResult {
+        self.invoke(client::cancel_offer_ix(self.program.key, escrow, initiator, rent_payer))
+    }
+
+    pub fn close_escrow(&self, keys: &EscrowKeys) -> ProgramResult {
+        self.invoke(client::close_escrow_ix(self.program.key, keys))
+    }
+
+    /// Snapshot of the escrow from the GetEscrowInfo return data
+    pub fn get_escrow_info(&self, keys: &EscrowKeys) -> Result<EscrowInfo, ProgramError> {
+        self.invoke(client::get_escrow_info_ix(self.program.key, keys))?;
+        let (return_program_id, data) = get_return_data().ok_or(ProgramError::InvalidAccountData)?;
+        client::decode_escrow_info(self.program.key, &return_program_id, &data).ok_or(ProgramError::InvalidAccountData)
+    }
+}
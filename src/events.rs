@@ -0,0 +1,244 @@
+//! Structured events emitted by the program.
+//!
+//! Each event is logged with `sol_log_data` as a single field: [`EVENT_DISCRIMINATOR`]
+//! followed by the Borsh-encoded [`EscrowEvent`]. It shows up in the transaction logs as
+//! `Program data: <base64>`. With the `client` feature, [`decode_logs`] turns the log
+//! messages of a transaction back into events.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{log::sol_log_data, pubkey::Pubkey};
+
+/// First 8 bytes of `sha256("event:EscrowEvent")`
+pub const EVENT_DISCRIMINATOR: [u8; 8] = [241, 51, 61, 3, 5, 32, 113, 144];
+
+/// State changes and fund movements of an escrow.
+/// Amounts are in lamports for SOL escrows and in token units otherwise.
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum EscrowEvent {
+    OfferCreated {
+        escrow: Pubkey,
+        initiator: Pubkey,
+        /// 0 = initiator is the buyer, 1 = the seller
+        role: u8,
+        amount: u64,
+        mint: Pubkey,
+        arbiter: Pubkey,
+    },
+    Joined {
+        escrow: Pubkey,
+        joiner: Pubkey,
+        role: u8,
+    },
+    /// `amount` is what arrived in the vault
+    Funded {
+        escrow: Pubkey,
+        buyer: Pubkey,
+        amount: u64,
+    },
+    /// Seller confirmed the whole escrow, or a single milestone
+    SellerConfirmed {
+        escrow: Pubkey,
+        seller: Pubkey,
+        milestone: Option<u8>,
+    },
+    Disputed {
+        escrow: Pubkey,
+        raised_by: Pubkey,
+    },
+    /// Vault paid out to the seller. `amount` is net of the settlement `fee` and `arbiter_fee`.
+    Released {
+        escrow: Pubkey,
+        seller: Pubkey,
+        amount: u64,
+        fee: u64,
+        arbiter_fee: u64,
+        milestone: Option<u8>,
+    },
+    /// Vault returned to the buyer, net of `arbiter_fee`. Zero for offers cancelled before funding.
+    Refunded {
+        escrow: Pubkey,
+        buyer: Pubkey,
+        amount: u64,
+        arbiter_fee: u64,
+        milestone: Option<u8>,
+    },
+    /// Arbiter split the vault between the parties
+    Settled {
+        escrow: Pubkey,
+        seller_amount: u64,
+        buyer_amount: u64,
+        fee: u64,
+        arbiter_fee: u64,
+    },
+    /// Escrow accounts closed and their lamports returned to `rent_payer`
+    Closed {
+        escrow: Pubkey,
+        rent_payer: Pubkey,
+        lamports: u64,
+    },
+    /// Seller bond deposited; `amount` is what arrived in the vault
+    CollateralFunded {
+        escrow: Pubkey,
+        seller: Pubkey,
+        amount: u64,
+    },
+    /// Seller bond paid out when the escrow settled: returned to the seller,
+    /// forfeited to the buyer, or split by the arbiter
+    CollateralReleased {
+        escrow: Pubkey,
+        seller_amount: u64,
+        buyer_amount: u64,
+    },
+}
+
+impl EscrowEvent {
+    /// Discriminator followed by the Borsh-encoded event
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = EVENT_DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&borsh::to_vec(self).unwrap());
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let payload = data.strip_prefix(&EVENT_DISCRIMINATOR[..])?;
+        Self::try_from_slice(payload).ok()
+    }
+
+    pub fn emit(&self) {
+        sol_log_data(&[&self.to_bytes()]);
+    }
+}
+
+/// Events emitted by `program_id` in the log messages of one transaction, in order.
+/// `Program data:` lines are attributed to the program on top of the invoke stack, so
+/// events logged by other programs (including look-alikes) are ignored.
+#[cfg(feature = "client")]
+pub fn decode_logs<S: AsRef<str>>(program_id: &Pubkey, logs: &[S]) -> Vec<EscrowEvent> {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    let program = program_id.to_string();
+    let mut invoke_stack: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+
+    for line in logs.iter().map(AsRef::as_ref) {
+        if let Some(data) = line.strip_prefix("Program data: ") {
+            if invoke_stack.last() != Some(&program.as_str()) {
+                continue;
+            }
+            let event = data
+                .split_whitespace()
+                .next()
+                .and_then(|field| STANDARD.decode(field).ok())
+                .and_then(|bytes| EscrowEvent::from_bytes(&bytes));
+            events.extend(event);
+        } else if let Some(rest) = line.strip_prefix("Program ") {
+            // `Program <id> invoke [n]` / `Program <id> success` / `Program <id> failed: ...`,
+            // as opposed to `Program log: ...` and `Program return: ...`
+            let mut words = rest.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some(id), _) if id.ends_with(':') => {}
+                (Some(id), Some("invoke")) => invoke_stack.push(id),
+                (Some(_), Some("success" | "failed:")) => {
+                    invoke_stack.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::{hash::hash, pubkey};
+
+    #[test]
+    fn test_discriminator() {
+        assert_eq!(hash(b"event:EscrowEvent").to_bytes()[..8], EVENT_DISCRIMINATOR);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let escrow = Pubkey::new_unique();
+        let party = Pubkey::new_unique();
+        let events = [
+            EscrowEvent::OfferCreated {
+                escrow,
+                initiator: party,
+                role: 1,
+                amount: 5,
+                mint: Pubkey::new_unique(),
+                arbiter: Pubkey::new_unique(),
+            },
+            EscrowEvent::Joined { escrow, joiner: party, role: 0 },
+            EscrowEvent::Funded { escrow, buyer: party, amount: 5 },
+            EscrowEvent::SellerConfirmed { escrow, seller: party, milestone: Some(2) },
+            EscrowEvent::Disputed { escrow, raised_by: party },
+            EscrowEvent::Released { escrow, seller: party, amount: 4, fee: 1, arbiter_fee: 0, milestone: None },
+            EscrowEvent::Refunded { escrow, buyer: party, amount: 5, arbiter_fee: 0, milestone: Some(0) },
+            EscrowEvent::Settled { escrow, seller_amount: 2, buyer_amount: 2, fee: 1, arbiter_fee: 1 },
+            EscrowEvent::Closed { escrow, rent_payer: party, lamports: 3_000_000 },
+            EscrowEvent::CollateralFunded { escrow, seller: party, amount: 5 },
+            EscrowEvent::CollateralReleased { escrow, seller_amount: 0, buyer_amount: 5 },
+        ];
+        for event in events {
+            let bytes = event.to_bytes();
+            assert_eq!(bytes[..8], EVENT_DISCRIMINATOR);
+            assert_eq!(EscrowEvent::from_bytes(&bytes), Some(event));
+            // Another program's data, or a truncated event
+            assert_eq!(EscrowEvent::from_bytes(&bytes[8..]), None);
+            assert_eq!(EscrowEvent::from_bytes(&bytes[..bytes.len() - 1]), None);
+        }
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn test_decode_captured_logs() {
+        const PROGRAM_ID: Pubkey = pubkey!("1111111QLbz7JHiBTspS962RLKV8GndWFwiEaqKM");
+        const OTHER_PROGRAM: &str = "Other11111111111111111111111111111111111111";
+        // FundEscrow as logged by the runtime
+        const FUNDED: &str = "Program data: 8TM9AwUgcZAC2SZ9Us+3BvBIga20TlD78YqD16q3VZr5GrMzuc0EJcxdhixEIkAI+F6Va7wOdBvQ7DpYlk6an8JAWWMKHVRoqwCUNXcAAAAA";
+        let funded = EscrowEvent::Funded {
+            escrow: pubkey!("FcfWdCwudiFd1ZuPYQVN9rgmrKybcBq4RMAcTVntZ9Fq"),
+            buyer: pubkey!("7J5ab5uYEp91b4y8zbJ69PbHzTUWXSuWLXxdiTxKBPVk"),
+            amount: 2_000_000_000,
+        };
+
+        let logs = [
+            "Program 1111111QLbz7JHiBTspS962RLKV8GndWFwiEaqKM invoke [1]",
+            "Program 11111111111111111111111111111111 invoke [2]",
+            "Program 11111111111111111111111111111111 success",
+            "Program log: Escrow funded successfully. Amount: 2000000000, received: 2000000000",
+            FUNDED,
+            "Program 1111111QLbz7JHiBTspS962RLKV8GndWFwiEaqKM consumed 9461 of 200000 compute units",
+            "Program 1111111QLbz7JHiBTspS962RLKV8GndWFwiEaqKM success",
+        ];
+        assert_eq!(decode_logs(&PROGRAM_ID, &logs), std::slice::from_ref(&funded));
+
+        // Identical data logged by another program, top-level or in a CPI from the escrow
+        // program, is not an escrow event
+        let other_invoke = format!("Program {OTHER_PROGRAM} invoke [2]");
+        let other_success = format!("Program {OTHER_PROGRAM} success");
+        let other_failed = format!("Program {OTHER_PROGRAM} failed: custom program error: 0x1");
+        let logs = [
+            &other_invoke.replace("[2]", "[1]"),
+            FUNDED,
+            &other_success,
+            "Program 1111111QLbz7JHiBTspS962RLKV8GndWFwiEaqKM invoke [1]",
+            &other_invoke,
+            FUNDED,
+            &other_success,
+            FUNDED,
+            &other_invoke,
+            FUNDED,
+            &other_failed,
+            "Program log: not an event",
+            "Program return: 1111111QLbz7JHiBTspS962RLKV8GndWFwiEaqKM AQ==",
+            "Program data: bm90IGFuIGV2ZW50",
+            "Program 1111111QLbz7JHiBTspS962RLKV8GndWFwiEaqKM success",
+            FUNDED,
+        ];
+        assert_eq!(decode_logs(&PROGRAM_ID, &logs), [funded]);
+    }
+}
@@ -1,61 +1,620 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
 use solana_program::{
-    program_error::ProgramError,
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+    pubkey::Pubkey,
 };
+use thiserror::Error;
 
-#[repr(u8)]
+use crate::utils::ValidationHelper;
+
+/// First byte of Borsh-encoded instruction data. Legacy instruction data starts
+/// with the instruction tag (0..=22) and keeps its original byte layout.
+pub const BORSH_INSTRUCTION_MARKER: u8 = 0xFF;
+
+/// Wire format used to encode an `EscrowInstruction`
 #[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InstructionVersion {
+    /// Instruction tag followed by the original fixed-offset layout
+    Legacy,
+    /// `BORSH_INSTRUCTION_MARKER` followed by the Borsh-encoded instruction
+    Borsh,
+}
+
+/// Terms of a new offer
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct CreateOfferArgs {
+    /// 0 = buyer creates, 1 = seller creates
+    pub role: u8,
+    pub amount: u64,
+    pub arbiter: Pubkey,
+    pub mint: Pubkey,
+    /// Random seed of the escrow PDA
+    pub seed: [u8; 32],
+    /// Seconds from creation, 0 = no deadline
+    pub funding_window: i64,
+    /// Seconds from creation, 0 = no deadline
+    pub delivery_window: i64,
+    /// Seconds from seller confirmation, 0 = no auto-release
+    pub dispute_window: i64,
+    pub arbiter_opt_in: bool,
+    /// 0 = fixed amount, 1 = basis points
+    pub arbiter_fee_mode: u8,
+    pub arbiter_fee: u64,
+    /// Collateral the seller deposits with FundCollateral before funding, 0 = none
+    pub seller_bond: u64,
+}
+
+/// Fee settings of the ProgramConfig
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct ConfigArgs {
+    pub fee_collector: Pubkey,
+    pub flat_fee: u64,
+    /// SPL escrows pay it at settlement in either mode; see `ProgramConfig::fee_bps`
+    pub fee_bps: u16,
+    /// 0 = charged upfront at creation, 1 = deducted at settlement
+    pub fee_on_settlement: u8,
+    pub cancel_refund_bps: u16,
+}
+
+/// Program instructions. Variant order matches the legacy instruction tags,
+/// so the Borsh variant index equals the legacy tag.
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
 pub enum EscrowInstruction {
-    CreateOffer = 0,
-    JoinOffer = 1,
-    FundEscrow = 2,
-    ConfirmEscrow = 3,
-    ArbiterConfirm = 4,
-    ArbiterCancel = 5,
-    CloseEscrow = 6,
-    GetEscrowInfo = 7,
-    MutualCancel = 8,
-    SellerConfirm = 9,
+    CreateOffer(CreateOfferArgs),
+    JoinOffer { role: u8, joiner: Pubkey, arbiter_opt_in: bool },
+    FundEscrow,
+    ConfirmEscrow,
+    ArbiterConfirm,
+    ArbiterCancel,
+    CloseEscrow,
+    GetEscrowInfo,
+    MutualCancel,
+    SellerConfirm,
+    ClaimExpiredRefund,
+    ClaimAutoRelease,
+    RaiseDispute,
+    /// mode 0 = `seller_share` in basis points, 1 = explicit seller amount
+    ArbiterSettle { mode: u8, seller_share: u64 },
+    InitMilestones { amounts: Vec<u64> },
+    ConfirmMilestone { index: u8 },
+    ReleaseMilestone { index: u8 },
+    /// ruling 0 = release to seller, 1 = refund to buyer
+    ArbiterRuleMilestone { index: u8, ruling: u8 },
+    InitializeConfig(ConfigArgs),
+    UpdateConfig { new_admin: Pubkey, config: ConfigArgs },
+    CancelOffer,
+    MigrateEscrow,
+    FundCollateral,
 }
 
 impl EscrowInstruction {
-    pub fn from_u8(value: u8) -> Result<Self, ProgramError> {
-        match value {
-            0 => Ok(EscrowInstruction::CreateOffer),
-            1 => Ok(EscrowInstruction::JoinOffer),
-            2 => Ok(EscrowInstruction::FundEscrow),
-            3 => Ok(EscrowInstruction::ConfirmEscrow),
-            4 => Ok(EscrowInstruction::ArbiterConfirm),
-            5 => Ok(EscrowInstruction::ArbiterCancel),
-            6 => Ok(EscrowInstruction::CloseEscrow),
-            7 => Ok(EscrowInstruction::GetEscrowInfo),
-            8 => Ok(EscrowInstruction::MutualCancel),
-            9 => Ok(EscrowInstruction::SellerConfirm),
-            _ => Err(ProgramError::InvalidInstructionData),
+    /// Decode instruction data in either wire format
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        match data.first() {
+            None => Err(ProgramError::InvalidInstructionData),
+            Some(&BORSH_INSTRUCTION_MARKER) => Self::try_from_slice(&data[1..]).map_err(|_| ProgramError::InvalidInstructionData),
+            Some(_) => Self::unpack_legacy(data),
         }
     }
+
+    /// Encode the instruction in the given wire format
+    pub fn pack(&self, version: InstructionVersion) -> Vec<u8> {
+        match version {
+            InstructionVersion::Legacy => self.pack_legacy(),
+            InstructionVersion::Borsh => {
+                let mut data = vec![BORSH_INSTRUCTION_MARKER];
+                data.extend_from_slice(&borsh::to_vec(self).unwrap());
+                data
+            }
+        }
+    }
+
+    /// Legacy instruction tag (also the Borsh variant index)
+    pub fn tag(&self) -> u8 {
+        match self {
+            Self::CreateOffer(_) => 0,
+            Self::JoinOffer { .. } => 1,
+            Self::FundEscrow => 2,
+            Self::ConfirmEscrow => 3,
+            Self::ArbiterConfirm => 4,
+            Self::ArbiterCancel => 5,
+            Self::CloseEscrow => 6,
+            Self::GetEscrowInfo => 7,
+            Self::MutualCancel => 8,
+            Self::SellerConfirm => 9,
+            Self::ClaimExpiredRefund => 10,
+            Self::ClaimAutoRelease => 11,
+            Self::RaiseDispute => 12,
+            Self::ArbiterSettle { .. } => 13,
+            Self::InitMilestones { .. } => 14,
+            Self::ConfirmMilestone { .. } => 15,
+            Self::ReleaseMilestone { .. } => 16,
+            Self::ArbiterRuleMilestone { .. } => 17,
+            Self::InitializeConfig(_) => 18,
+            Self::UpdateConfig { .. } => 19,
+            Self::CancelOffer => 20,
+            Self::MigrateEscrow => 21,
+            Self::FundCollateral => 22,
+        }
+    }
+
+    fn unpack_legacy(data: &[u8]) -> Result<Self, ProgramError> {
+        let pubkey_at = |offset: usize| Pubkey::new_from_array(data[offset..offset + 32].try_into().unwrap());
+        let u64_at = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+        let i64_at = |offset: usize| i64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+        let u16_at = |offset: usize| u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap());
+
+        Ok(match data[0] {
+            0 => {
+                // Trailing fields were added over time and may be omitted by older clients.
+                // Bytes 74-105 held the fee collector, which now comes from ProgramConfig.
+                ValidationHelper::validate_instruction_data_length_in(data, &[138, 154, 162, 163, 172, 180], "CreateOffer")?;
+                let len = data.len();
+                Self::CreateOffer(CreateOfferArgs {
+                    role: data[1],
+                    amount: u64_at(2),
+                    arbiter: pubkey_at(10),
+                    mint: pubkey_at(42),
+                    seed: data[106..138].try_into().unwrap(),
+                    funding_window: if len >= 154 { i64_at(138) } else { 0 },
+                    delivery_window: if len >= 154 { i64_at(146) } else { 0 },
+                    dispute_window: if len >= 162 { i64_at(154) } else { 0 },
+                    arbiter_opt_in: len >= 163 && data[162] != 0,
+                    arbiter_fee_mode: if len >= 172 { data[163] } else { 0 },
+                    arbiter_fee: if len >= 172 { u64_at(164) } else { 0 },
+                    seller_bond: if len >= 180 { u64_at(172) } else { 0 },
+                })
+            }
+            1 => {
+                ValidationHelper::validate_instruction_data_length_in(data, &[34, 35], "JoinOffer")?;
+                Self::JoinOffer {
+                    role: data[1],
+                    joiner: pubkey_at(2),
+                    arbiter_opt_in: data.len() >= 35 && data[34] != 0,
+                }
+            }
+            2 => Self::FundEscrow,
+            3 => Self::ConfirmEscrow,
+            4 => Self::ArbiterConfirm,
+            5 => Self::ArbiterCancel,
+            6 => Self::CloseEscrow,
+            7 => Self::GetEscrowInfo,
+            8 => Self::MutualCancel,
+            9 => Self::SellerConfirm,
+            10 => Self::ClaimExpiredRefund,
+            11 => Self::ClaimAutoRelease,
+            12 => Self::RaiseDispute,
+            13 => {
+                ValidationHelper::validate_instruction_data_length(data, 10, "ArbiterSettle")?;
+                Self::ArbiterSettle {
+                    mode: data[1],
+                    seller_share: u64_at(2),
+                }
+            }
+            14 => {
+                if data.len() < 2 {
+                    return Err(ProgramError::InvalidInstructionData);
+                }
+                let count = data[1] as usize;
+                ValidationHelper::validate_instruction_data_length(data, 2 + count * 8, "InitMilestones")?;
+                Self::InitMilestones {
+                    amounts: (0..count).map(|i| u64_at(2 + i * 8)).collect(),
+                }
+            }
+            15 => {
+                ValidationHelper::validate_instruction_data_length(data, 2, "ConfirmMilestone")?;
+                Self::ConfirmMilestone { index: data[1] }
+            }
+            16 => {
+                ValidationHelper::validate_instruction_data_length(data, 2, "ReleaseMilestone")?;
+                Self::ReleaseMilestone { index: data[1] }
+            }
+            17 => {
+                ValidationHelper::validate_instruction_data_length(data, 3, "ArbiterRuleMilestone")?;
+                Self::ArbiterRuleMilestone {
+                    index: data[1],
+                    ruling: data[2],
+                }
+            }
+            18 => {
+                ValidationHelper::validate_instruction_data_length(data, 46, "InitializeConfig")?;
+                Self::InitializeConfig(ConfigArgs {
+                    fee_collector: pubkey_at(1),
+                    flat_fee: u64_at(33),
+                    fee_bps: u16_at(41),
+                    fee_on_settlement: data[43],
+                    cancel_refund_bps: u16_at(44),
+                })
+            }
+            19 => {
+                ValidationHelper::validate_instruction_data_length(data, 78, "UpdateConfig")?;
+                Self::UpdateConfig {
+                    new_admin: pubkey_at(1),
+                    config: ConfigArgs {
+                        fee_collector: pubkey_at(33),
+                        flat_fee: u64_at(65),
+                        fee_bps: u16_at(73),
+                        fee_on_settlement: data[75],
+                        cancel_refund_bps: u16_at(76),
+                    },
+                }
+            }
+            20 => Self::CancelOffer,
+            21 => Self::MigrateEscrow,
+            22 => Self::FundCollateral,
+            _ => return Err(ProgramError::InvalidInstructionData),
+        })
+    }
+
+    fn pack_legacy(&self) -> Vec<u8> {
+        let mut data = vec![self.tag()];
+        match self {
+            Self::CreateOffer(args) => {
+                data.push(args.role);
+                data.extend_from_slice(&args.amount.to_le_bytes());
+                data.extend_from_slice(args.arbiter.as_ref());
+                data.extend_from_slice(args.mint.as_ref());
+                data.extend_from_slice(&[0u8; 32]); // fee collector slot, ignored
+                data.extend_from_slice(&args.seed);
+                data.extend_from_slice(&args.funding_window.to_le_bytes());
+                data.extend_from_slice(&args.delivery_window.to_le_bytes());
+                data.extend_from_slice(&args.dispute_window.to_le_bytes());
+                data.push(args.arbiter_opt_in as u8);
+                data.push(args.arbiter_fee_mode);
+                data.extend_from_slice(&args.arbiter_fee.to_le_bytes());
+                data.extend_from_slice(&args.seller_bond.to_le_bytes());
+            }
+            Self::JoinOffer { role, joiner, arbiter_opt_in } => {
+                data.push(*role);
+                data.extend_from_slice(joiner.as_ref());
+                data.push(*arbiter_opt_in as u8);
+            }
+            Self::ArbiterSettle { mode, seller_share } => {
+                data.push(*mode);
+                data.extend_from_slice(&seller_share.to_le_bytes());
+            }
+            Self::InitMilestones { amounts } => {
+                data.push(amounts.len() as u8);
+                for amount in amounts {
+                    data.extend_from_slice(&amount.to_le_bytes());
+                }
+            }
+            Self::ConfirmMilestone { index } | Self::ReleaseMilestone { index } => data.push(*index),
+            Self::ArbiterRuleMilestone { index, ruling } => {
+                data.push(*index);
+                data.push(*ruling);
+            }
+            Self::InitializeConfig(config) => config.pack_legacy(&mut data),
+            Self::UpdateConfig { new_admin, config } => {
+                data.extend_from_slice(new_admin.as_ref());
+                config.pack_legacy(&mut data);
+            }
+            _ => {}
+        }
+        data
+    }
+}
+
+impl ConfigArgs {
+    fn pack_legacy(&self, data: &mut Vec<u8>) {
+        data.extend_from_slice(self.fee_collector.as_ref());
+        data.extend_from_slice(&self.flat_fee.to_le_bytes());
+        data.extend_from_slice(&self.fee_bps.to_le_bytes());
+        data.push(self.fee_on_settlement);
+        data.extend_from_slice(&self.cancel_refund_bps.to_le_bytes());
+    }
 }
 
-#[derive(Debug)]
+/// Program errors, returned as `ProgramError::Custom(code)`.
+/// Codes are stable: new variants are only ever appended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error, FromPrimitive)]
 pub enum EscrowError {
-    InvalidRole,
-    InvalidState,
-    InvalidParty,
-    AccountAlreadySet,
-    InsufficientFunds,
-    InvalidVault,
-    InvalidMint,
+    #[error("Invalid role")]
+    InvalidRole = 100,
+    #[error("Escrow is not in a state that allows this instruction")]
+    InvalidState = 101,
+    #[error("Account does not match the expected escrow party")]
+    InvalidParty = 102,
+    #[error("Account already set")]
+    AccountAlreadySet = 103,
+    #[error("Insufficient funds")]
+    InsufficientFunds = 104,
+    #[error("Invalid vault account")]
+    InvalidVault = 105,
+    #[error("Invalid mint")]
+    InvalidMint = 106,
+    #[error("Token account mint does not match the escrow mint")]
+    TokenAccountMintMismatch = 107,
+    #[error("Token account owner does not match the expected owner")]
+    TokenAccountOwnerMismatch = 108,
+    #[error("Invalid token account")]
+    InvalidTokenAccount = 109,
+    #[error("Vault token account is not the vault's associated token account")]
+    InvalidVaultTokenAccount = 110,
+    #[error("Vault token account still holds tokens")]
+    VaultNotEmpty = 111,
+    #[error("Account discriminator does not match the expected account type")]
+    InvalidAccountDiscriminator = 112,
+    #[error("Not the escrow buyer")]
+    NotBuyer = 113,
+    #[error("Not the escrow seller")]
+    NotSeller = 114,
+    #[error("Not the escrow arbiter")]
+    NotArbiter = 115,
+    #[error("Only the buyer or the seller may do this")]
+    NotBuyerOrSeller = 116,
+    #[error("Not the offer initiator")]
+    NotInitiator = 117,
+    #[error("Not the recorded rent payer")]
+    NotRentPayer = 118,
+    #[error("Not the config admin")]
+    NotConfigAdmin = 119,
+    #[error("Not the program upgrade authority")]
+    NotUpgradeAuthority = 120,
+    #[error("Deadline not reached yet")]
+    DeadlineNotReached = 121,
+    #[error("Deadline has passed")]
+    DeadlinePassed = 122,
+    #[error("Buyer dispute window has not elapsed")]
+    DisputeWindowActive = 123,
+    #[error("Escrow must be disputed, or both parties must opt in to arbitration")]
+    ArbitrationNotAllowed = 124,
+    #[error("Invalid amount")]
+    InvalidAmount = 125,
+    #[error("Invalid arbiter fee")]
+    InvalidArbiterFee = 126,
+    #[error("Invalid deadline window")]
+    InvalidDeadline = 127,
+    #[error("Invalid ruling or settlement mode")]
+    InvalidRuling = 128,
+    #[error("Invalid milestone count")]
+    InvalidMilestoneCount = 129,
+    #[error("Invalid milestone index")]
+    InvalidMilestoneIndex = 130,
+    #[error("Milestone is not in a state that allows this instruction")]
+    InvalidMilestoneState = 131,
+    #[error("Invalid milestone account")]
+    InvalidMilestoneAccount = 132,
+    #[error("Milestone escrows are settled per milestone")]
+    MilestoneEscrow = 133,
+    #[error("Basis points must not exceed 10000")]
+    InvalidBasisPoints = 134,
+    #[error("Invalid fee collector")]
+    InvalidFeeCollector = 135,
+    #[error("Escrow address does not match its seed")]
+    InvalidEscrowAddress = 136,
+    #[error("Invalid config account")]
+    InvalidConfigAccount = 137,
+    #[error("Invalid program data account")]
+    InvalidProgramData = 138,
+    #[error("Account is not owned by the escrow program")]
+    InvalidAccountOwner = 139,
+    #[error("Escrow uses a legacy layout, run MigrateEscrow first")]
+    AccountNeedsMigration = 140,
+    #[error("Invalid account data layout")]
+    InvalidAccountLayout = 141,
+    #[error("Seller collateral must be deposited before funding")]
+    CollateralNotFunded = 142,
+    #[error("No seller collateral is due")]
+    NoCollateralDue = 143,
+    #[error("Milestone escrows do not support seller collateral")]
+    CollateralNotSupported = 144,
+    #[error("Seller-confirmed milestones must be released or ruled on first")]
+    MilestonesAwaitingRelease = 145,
+}
+
+impl EscrowError {
+    /// Error for a `ProgramError::Custom` code, `None` for codes this program never returns
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::from_u32(code)
+    }
 }
 
 impl From<EscrowError> for ProgramError {
     fn from(e: EscrowError) -> Self {
-        match e {
-            EscrowError::InvalidRole => ProgramError::Custom(100),
-            EscrowError::InvalidState => ProgramError::Custom(101),
-            EscrowError::InvalidParty => ProgramError::Custom(102),
-            EscrowError::AccountAlreadySet => ProgramError::Custom(103),
-            EscrowError::InsufficientFunds => ProgramError::Custom(104),
-            EscrowError::InvalidVault => ProgramError::Custom(105),
-            EscrowError::InvalidMint => ProgramError::Custom(106),
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for EscrowError {
+    fn type_of() -> &'static str {
+        "EscrowError"
+    }
+}
+
+impl PrintProgramError for EscrowError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+    {
+        msg!("Error: {}", self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_args() -> ConfigArgs {
+        ConfigArgs {
+            fee_collector: Pubkey::new_unique(),
+            flat_fee: 10_000_000,
+            fee_bps: 150,
+            fee_on_settlement: 1,
+            cancel_refund_bps: 5_000,
+        }
+    }
+
+    fn all_instructions() -> Vec<EscrowInstruction> {
+        vec![
+            EscrowInstruction::CreateOffer(CreateOfferArgs {
+                role: 1,
+                amount: 5_000_000_000,
+                arbiter: Pubkey::new_unique(),
+                mint: Pubkey::new_unique(),
+                seed: [7u8; 32],
+                funding_window: 3_600,
+                delivery_window: 86_400,
+                dispute_window: 172_800,
+                arbiter_opt_in: true,
+                arbiter_fee_mode: 1,
+                arbiter_fee: 250,
+                seller_bond: 1_000_000_000,
+            }),
+            EscrowInstruction::JoinOffer { role: 0, joiner: Pubkey::new_unique(), arbiter_opt_in: true },
+            EscrowInstruction::FundEscrow,
+            EscrowInstruction::ConfirmEscrow,
+            EscrowInstruction::ArbiterConfirm,
+            EscrowInstruction::ArbiterCancel,
+            EscrowInstruction::CloseEscrow,
+            EscrowInstruction::GetEscrowInfo,
+            EscrowInstruction::MutualCancel,
+            EscrowInstruction::SellerConfirm,
+            EscrowInstruction::ClaimExpiredRefund,
+            EscrowInstruction::ClaimAutoRelease,
+            EscrowInstruction::RaiseDispute,
+            EscrowInstruction::ArbiterSettle { mode: 0, seller_share: 7_500 },
+            EscrowInstruction::InitMilestones { amounts: vec![1_000, 2_000, 3_000] },
+            EscrowInstruction::ConfirmMilestone { index: 2 },
+            EscrowInstruction::ReleaseMilestone { index: 1 },
+            EscrowInstruction::ArbiterRuleMilestone { index: 0, ruling: 1 },
+            EscrowInstruction::InitializeConfig(config_args()),
+            EscrowInstruction::UpdateConfig { new_admin: Pubkey::new_unique(), config: config_args() },
+            EscrowInstruction::CancelOffer,
+            EscrowInstruction::MigrateEscrow,
+            EscrowInstruction::FundCollateral,
+        ]
+    }
+
+    #[test]
+    fn test_round_trip_both_versions() {
+        for (tag, instruction) in all_instructions().into_iter().enumerate() {
+            assert_eq!(instruction.tag() as usize, tag);
+
+            let legacy = instruction.pack(InstructionVersion::Legacy);
+            assert_eq!(legacy[0] as usize, tag);
+            assert_eq!(EscrowInstruction::unpack(&legacy).unwrap(), instruction);
+
+            let borsh = instruction.pack(InstructionVersion::Borsh);
+            assert_eq!(borsh[0], BORSH_INSTRUCTION_MARKER);
+            assert_eq!(borsh[1] as usize, tag);
+            assert_eq!(EscrowInstruction::unpack(&borsh).unwrap(), instruction);
+        }
+    }
+
+    #[test]
+    fn test_legacy_layout_offsets() {
+        let instruction = &all_instructions()[0];
+        let EscrowInstruction::CreateOffer(args) = instruction else { unreachable!() };
+        let data = instruction.pack(InstructionVersion::Legacy);
+
+        assert_eq!(data.len(), 180);
+        assert_eq!(&data[2..10], &args.amount.to_le_bytes());
+        assert_eq!(&data[10..42], args.arbiter.as_ref());
+        assert_eq!(&data[42..74], args.mint.as_ref());
+        assert_eq!(&data[106..138], &args.seed);
+        assert_eq!(&data[164..172], &args.arbiter_fee.to_le_bytes());
+        assert_eq!(&data[172..180], &args.seller_bond.to_le_bytes());
+
+        let instruction = EscrowInstruction::UpdateConfig { new_admin: Pubkey::new_unique(), config: config_args() };
+        assert_eq!(instruction.pack(InstructionVersion::Legacy).len(), 78);
+    }
+
+    #[test]
+    fn test_legacy_create_offer_short_forms() {
+        let full = all_instructions()[0].pack(InstructionVersion::Legacy);
+
+        let EscrowInstruction::CreateOffer(args) = EscrowInstruction::unpack(&full[..138]).unwrap() else {
+            unreachable!()
+        };
+        assert_eq!(args.amount, 5_000_000_000);
+        assert_eq!(args.seed, [7u8; 32]);
+        assert_eq!((args.funding_window, args.delivery_window, args.dispute_window), (0, 0, 0));
+        assert!(!args.arbiter_opt_in);
+        assert_eq!((args.arbiter_fee_mode, args.arbiter_fee), (0, 0));
+
+        let EscrowInstruction::CreateOffer(args) = EscrowInstruction::unpack(&full[..163]).unwrap() else {
+            unreachable!()
+        };
+        assert_eq!(args.dispute_window, 172_800);
+        assert!(args.arbiter_opt_in);
+        assert_eq!(args.arbiter_fee, 0);
+
+        let EscrowInstruction::CreateOffer(args) = EscrowInstruction::unpack(&full[..172]).unwrap() else {
+            unreachable!()
+        };
+        assert_eq!(args.arbiter_fee, 250);
+        assert_eq!(args.seller_bond, 0);
+
+        let joiner = Pubkey::new_unique();
+        let mut legacy_join = vec![1u8, 0];
+        legacy_join.extend_from_slice(joiner.as_ref());
+        assert_eq!(
+            EscrowInstruction::unpack(&legacy_join).unwrap(),
+            EscrowInstruction::JoinOffer { role: 0, joiner, arbiter_opt_in: false }
+        );
+    }
+
+    #[test]
+    fn test_malformed_data_rejected() {
+        let full = all_instructions()[0].pack(InstructionVersion::Legacy);
+        for data in [&[][..], &full[..137], &full[..150], &[23u8][..], &[14u8][..], &[14u8, 2, 0, 0][..], &[13u8, 0][..]] {
+            assert_eq!(EscrowInstruction::unpack(data), Err(ProgramError::InvalidInstructionData));
+        }
+
+        let mut borsh = all_instructions()[13].pack(InstructionVersion::Borsh);
+        borsh.push(0);
+        assert_eq!(EscrowInstruction::unpack(&borsh), Err(ProgramError::InvalidInstructionData));
+        assert_eq!(
+            EscrowInstruction::unpack(&[BORSH_INSTRUCTION_MARKER, 23]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(EscrowInstruction::unpack(&[BORSH_INSTRUCTION_MARKER]), Err(ProgramError::InvalidInstructionData));
+    }
+
+    #[test]
+    fn test_borsh_create_offer_requires_bond() {
+        // `seller_bond` is part of the Borsh format, a CreateOffer without it is truncated
+        let full = all_instructions()[0].pack(InstructionVersion::Borsh);
+        assert_eq!(
+            EscrowInstruction::unpack(&full[..full.len() - 8]),
+            Err(ProgramError::InvalidInstructionData)
+        );
+    }
+
+    #[test]
+    fn test_error_codes_are_stable() {
+        let pinned = [
+            (EscrowError::InvalidRole, 100),
+            (EscrowError::InvalidState, 101),
+            (EscrowError::InvalidParty, 102),
+            (EscrowError::InvalidVault, 105),
+            (EscrowError::InvalidAccountDiscriminator, 112),
+            (EscrowError::NotBuyer, 113),
+            (EscrowError::DeadlineNotReached, 121),
+            (EscrowError::InvalidAccountLayout, 141),
+            (EscrowError::CollateralNotSupported, 144),
+            (EscrowError::MilestonesAwaitingRelease, 145),
+        ];
+        for (error, code) in pinned {
+            assert_eq!(ProgramError::from(error), ProgramError::Custom(code));
+        }
+
+        // Codes are contiguous and every one decodes back to its variant
+        for code in 100..=145 {
+            let error = EscrowError::from_code(code).unwrap();
+            assert_eq!(error as u32, code);
+            assert!(!error.to_string().is_empty());
         }
+        assert_eq!(EscrowError::from_code(99), None);
+        assert_eq!(EscrowError::from_code(146), None);
+        assert_eq!(
+            <EscrowError as DecodeError<EscrowError>>::decode_custom_error_to_enum(124),
+            Some(EscrowError::ArbitrationNotAllowed)
+        );
     }
-}
\ No newline at end of file
+}
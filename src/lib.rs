@@ -4,59 +4,76 @@
 //! between two parties with an optional arbiter for dispute resolution.
 //! 
 //! ## Features
-//! - Support for both SOL and SPL token escrows
+//! - Support for both SOL and SPL token escrows (SPL Token and Token-2022)
 //! - Three-party system: buyer, seller, and arbiter
-//! - Multiple confirmation flows for secure transactions
-//! - Mutual cancellation support
+//! - Deadlines, disputes, arbiter rulings and milestone escrows; see the handlers for details
+//! - Admin-configurable protocol fee via a ProgramConfig PDA
 //! - PDA-based vault system for secure fund storage
+//! - Off-chain builders and CPI helpers behind the `client` and `cpi` features
 //!
 //! ## Security
 //! - All account ownership validations
 //! - PDA verification for vault accounts
+//! - Mint and owner checks on every token account, vault token account must be the vault ATA
 //! - Overflow protection on arithmetic operations
 //! - No unsafe code
 
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint,
     entrypoint::ProgramResult,
     msg,
-    program::invoke,
-    program_error::ProgramError,
+    program::{invoke, set_return_data},
+    clock::Clock,
+    program_error::{PrintProgramError, ProgramError},
     pubkey::Pubkey,
     rent::Rent,
     system_instruction,
     sysvar::Sysvar,
 };
 
-mod state;
-mod instructions;
-mod utils;
-
-use state::{EscrowAccount, EscrowState};
-use instructions::EscrowInstruction;
-use utils::{TokenTransfer, ValidationHelper, AccountHelper};
-
-/// Service fee for creating an order (0.01 SOL in lamports)
-const SERVICE_FEE: u64 = 10_000_000;
+pub mod state;
+pub mod instructions;
+pub mod utils;
+pub mod pda;
+pub mod transition;
+pub mod events;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "cpi")]
+pub mod cpi;
+
+use state::{
+    EscrowAccount, EscrowInfo, EscrowState, Milestone, MilestoneAccount, MilestoneStatus, ProgramConfig,
+    ARBITER_FEE_BPS, ARBITER_FEE_FIXED, ARBITER_OPT_IN_BUYER, ARBITER_OPT_IN_SELLER, ESCROW_VERSION,
+};
+use instructions::{ConfigArgs, CreateOfferArgs, EscrowError, EscrowInstruction};
+use pda::{CONFIG_SEED, ESCROW_SEED, MILESTONE_SEED, VAULT_SEED};
+use transition::{transition, Action, Actor, Conditions};
+use events::EscrowEvent;
+use utils::{
+    TokenTransfer, ValidationHelper, AccountHelper, AmountHelper, VaultDeposit, VaultPayout, BASIS_POINTS_DENOMINATOR,
+};
 
-entrypoint!(process_instruction);
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
 
 /// Main program entrypoint
-fn process_instruction(
+///
+/// Instruction data is decoded by `EscrowInstruction::unpack`: either the legacy
+/// layout documented on each handler, or `BORSH_INSTRUCTION_MARKER` followed by
+/// the Borsh-encoded `EscrowInstruction`.
+pub fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    if instruction_data.is_empty() {
-        return Err(ProgramError::InvalidInstructionData);
-    }
-    
-    let instruction = EscrowInstruction::from_u8(instruction_data[0])?;
+    let instruction = EscrowInstruction::unpack(instruction_data)?;
     
-    match instruction {
-        EscrowInstruction::CreateOffer => create_offer(program_id, accounts, instruction_data),
-        EscrowInstruction::JoinOffer => join_offer(program_id, accounts, instruction_data),
+    let result = match instruction {
+        EscrowInstruction::CreateOffer(args) => create_offer(program_id, accounts, args),
+        EscrowInstruction::JoinOffer { role, joiner, arbiter_opt_in } => {
+            join_offer(program_id, accounts, role, joiner, arbiter_opt_in)
+        }
         EscrowInstruction::FundEscrow => fund_escrow(program_id, accounts),
         EscrowInstruction::ConfirmEscrow => confirm_escrow(program_id, accounts),
         EscrowInstruction::ArbiterConfirm => arbiter_confirm(program_id, accounts),
@@ -65,7 +82,31 @@ fn process_instruction(
         EscrowInstruction::GetEscrowInfo => get_escrow_info(program_id, accounts),
         EscrowInstruction::MutualCancel => mutual_cancel(program_id, accounts),
         EscrowInstruction::SellerConfirm => seller_confirm(program_id, accounts),
+        EscrowInstruction::ClaimExpiredRefund => claim_expired_refund(program_id, accounts),
+        EscrowInstruction::ClaimAutoRelease => claim_auto_release(program_id, accounts),
+        EscrowInstruction::RaiseDispute => raise_dispute(program_id, accounts),
+        EscrowInstruction::ArbiterSettle { mode, seller_share } => {
+            arbiter_settle(program_id, accounts, mode, seller_share)
+        }
+        EscrowInstruction::InitMilestones { amounts } => init_milestones(program_id, accounts, amounts),
+        EscrowInstruction::ConfirmMilestone { index } => confirm_milestone(program_id, accounts, index),
+        EscrowInstruction::ReleaseMilestone { index } => release_milestone(program_id, accounts, index),
+        EscrowInstruction::ArbiterRuleMilestone { index, ruling } => {
+            arbiter_rule_milestone(program_id, accounts, index, ruling)
+        }
+        EscrowInstruction::InitializeConfig(config) => initialize_config(program_id, accounts, config),
+        EscrowInstruction::UpdateConfig { new_admin, config } => {
+            update_config(program_id, accounts, new_admin, config)
+        }
+        EscrowInstruction::CancelOffer => cancel_offer(program_id, accounts),
+        EscrowInstruction::MigrateEscrow => migrate_escrow(program_id, accounts),
+        EscrowInstruction::FundCollateral => fund_collateral(program_id, accounts),
+    };
+
+    if let Err(error) = &result {
+        error.print::<EscrowError>();
     }
+    result
 }
 
 /// Creates a new escrow offer
@@ -73,10 +114,13 @@ fn process_instruction(
 /// # Accounts
 /// * `[signer]` initiator - Party creating the order (buyer or seller)
 /// * `[writable]` escrow_account - PDA for storing order data
-/// * `[writable]` vault - PDA for storing funds
+/// * `[writable]` vault - PDA for storing funds (also holds the refundable part of the service fee until joined)
 /// * `[]` system_program - System program
 /// * `[]` mint - SPL token mint (native mint for SOL)
-/// * `[writable]` fee_collector - Service account for collecting fees
+/// * `[writable]` fee_collector - Fee collector from ProgramConfig
+/// * `[]` config - ProgramConfig PDA
+/// * `[signer, writable]` payer - Pays the service fee and rent instead of the initiator (optional).
+///   Recorded as the rent payer: it gets the rent and any cancellation refund back, while only the initiator may cancel.
 /// 
 /// # Instruction Data
 /// * byte 0: instruction type (0)
@@ -84,26 +128,65 @@ fn process_instruction(
 /// * bytes 2-9: amount (u64, little-endian)
 /// * bytes 10-41: arbiter pubkey (32 bytes)
 /// * bytes 42-73: mint pubkey (32 bytes)
-/// * bytes 74-105: fee_collector pubkey (32 bytes)
+/// * bytes 74-105: fee_collector pubkey (32 bytes, ignored: the collector comes from ProgramConfig)
 /// * bytes 106-137: random_seed for anonymity (32 bytes)
+/// * bytes 138-145: funding window in seconds (i64, optional, 0 = no deadline)
+/// * bytes 146-153: delivery window in seconds (i64, optional, 0 = no deadline)
+/// * bytes 154-161: dispute window in seconds (i64, optional, 0 = no auto-release)
+/// * byte 162: initiator lets the arbiter rule without a dispute (optional, 0 = no, 1 = yes)
+/// * byte 163: arbiter fee mode (optional, 0 = fixed amount, 1 = basis points)
+/// * bytes 164-171: arbiter fee (u64, optional, amount in the escrow's mint or basis points)
+/// * bytes 172-179: seller bond (u64, optional, 0 = none), deposited with FundCollateral before funding
+///
+/// Funding and delivery windows are counted from the creation time, the dispute
+/// window from seller confirmation. Legacy clients may omit the trailing fields.
 fn create_offer(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    instruction_data: &[u8],
+    args: CreateOfferArgs,
 ) -> ProgramResult {
-    ValidationHelper::validate_instruction_data_length(instruction_data, 138, "CreateOffer")?;
-    
-    let role = instruction_data[1];
-    let amount = u64::from_le_bytes(instruction_data[2..10].try_into().unwrap());
-    let arbiter = Pubkey::new_from_array(instruction_data[10..42].try_into().unwrap());
-    let mint = Pubkey::new_from_array(instruction_data[42..74].try_into().unwrap());
-    let fee_collector = Pubkey::new_from_array(instruction_data[74..106].try_into().unwrap());
-    let random_seed: [u8; 32] = instruction_data[106..138].try_into().unwrap();
+    let CreateOfferArgs {
+        role,
+        amount,
+        arbiter,
+        mint,
+        seed: random_seed,
+        funding_window,
+        delivery_window,
+        dispute_window,
+        arbiter_opt_in,
+        arbiter_fee_mode,
+        arbiter_fee,
+        seller_bond,
+    } = args;
+
+    ValidationHelper::validate_role(role)?;
 
     // Validate amount is not zero
     if amount == 0 {
         msg!("Amount must be greater than zero");
-        return Err(ProgramError::InvalidInstructionData);
+        return Err(EscrowError::InvalidAmount.into());
+    }
+
+    // Validate arbiter fee
+    let arbiter_fee_valid = match arbiter_fee_mode {
+        ARBITER_FEE_FIXED => arbiter_fee <= amount,
+        ARBITER_FEE_BPS => arbiter_fee <= BASIS_POINTS_DENOMINATOR as u64,
+        _ => false,
+    };
+    if !arbiter_fee_valid {
+        msg!("Invalid arbiter fee: mode {}, fee {}", arbiter_fee_mode, arbiter_fee);
+        return Err(EscrowError::InvalidArbiterFee.into());
+    }
+
+    // Validate deadline windows
+    if funding_window < 0 || delivery_window < 0 || dispute_window < 0 {
+        msg!("Deadline windows must not be negative");
+        return Err(EscrowError::InvalidDeadline.into());
+    }
+    if funding_window != 0 && delivery_window != 0 && delivery_window < funding_window {
+        msg!("Delivery window must not be shorter than funding window");
+        return Err(EscrowError::InvalidDeadline.into());
     }
 
     let accounts_iter = &mut accounts.iter();
@@ -113,20 +196,44 @@ fn create_offer(
     let system_program = next_account_info(accounts_iter)?;
     let _mint_account = next_account_info(accounts_iter)?;
     let fee_collector_account = next_account_info(accounts_iter)?;
+    let config_account = next_account_info(accounts_iter)?;
+    // Lets an initiator that cannot be debited by the system program, like a PDA holding data, create offers
+    let payer = next_account_info(accounts_iter).unwrap_or(initiator);
 
     // Validations
     ValidationHelper::validate_signer(initiator, "Initiator")?;
+    ValidationHelper::validate_signer(payer, "Payer")?;
     ValidationHelper::validate_system_program(system_program)?;
+
+    let config = ValidationHelper::load_config(config_account, program_id)?;
+    let fee_collector = config.fee_collector;
     ValidationHelper::validate_fee_collector(fee_collector_account, &fee_collector)?;
 
+    // Upfront: flat fee plus percentage of the amount. SPL offers pay the flat fee upfront
+    // and the percentage in their own mint at settlement, as lamports cannot stand in for it.
+    // On settlement: nothing upfront, the rate is locked into the escrow instead.
+    let (service_fee, settlement_fee_bps) = if config.fee_on_settlement != 0 {
+        (0, config.fee_bps)
+    } else if TokenTransfer::is_native_mint(&mint) {
+        let percentage_fee = AmountHelper::basis_points_of(amount, config.fee_bps)?;
+        let service_fee = config
+            .flat_fee
+            .checked_add(percentage_fee)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        (service_fee, 0)
+    } else {
+        (config.flat_fee, config.fee_bps)
+    };
+
+    // The refundable share of the fee waits in the vault until the offer is joined
+    let held_fee = AmountHelper::basis_points_of(service_fee, config.cancel_refund_bps)?;
+    let collected_fee = service_fee - held_fee;
+
     // Calculate vault PDA
-    let (vault_pda, vault_bump) = Pubkey::find_program_address(
-        &[b"vault", escrow_account.key.as_ref()],
-        program_id,
-    );
+    let (vault_pda, vault_bump) = pda::find_vault_address(program_id, escrow_account.key);
     if vault_pda != *vault.key {
         msg!("Invalid vault PDA");
-        return Err(ProgramError::InvalidSeeds);
+        return Err(EscrowError::InvalidVault.into());
     }
 
     let rent = Rent::get()?;
@@ -134,19 +241,21 @@ fn create_offer(
     let vault_rent = rent.minimum_balance(0);
 
     // Calculate total cost with overflow protection
-    let total_cost = SERVICE_FEE
+    let total_cost = service_fee
         .checked_add(escrow_rent)
         .and_then(|x| x.checked_add(vault_rent))
         .ok_or(ProgramError::ArithmeticOverflow)?;
 
-    ValidationHelper::validate_sufficient_balance(initiator, total_cost, "escrow creation")?;
+    ValidationHelper::validate_sufficient_balance(payer, total_cost, "escrow creation")?;
 
     // Transfer service fee
-    invoke(
-        &system_instruction::transfer(initiator.key, fee_collector_account.key, SERVICE_FEE),
-        &[initiator.clone(), fee_collector_account.clone(), system_program.clone()],
-    )?;
-    msg!("Service fee {} lamports transferred to {}", SERVICE_FEE, fee_collector);
+    if collected_fee > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, fee_collector_account.key, collected_fee),
+            &[payer.clone(), fee_collector_account.clone(), system_program.clone()],
+        )?;
+        msg!("Service fee {} lamports transferred to {}", collected_fee, fee_collector);
+    }
 
     // Create escrow account if not exists
     if escrow_account.lamports() == 0 {
@@ -156,11 +265,11 @@ fn create_offer(
             program_id
         )?;
         AccountHelper::create_pda_account(
-            initiator,
+            payer,
             escrow_account,
             system_program,
             program_id,
-            &[b"escrow", &random_seed, &[escrow_bump]],
+            &[ESCROW_SEED, &random_seed, &[escrow_bump]],
             EscrowAccount::LEN as u64,
             escrow_rent,
         )?;
@@ -173,6 +282,16 @@ fn create_offer(
         (Pubkey::default(), *initiator.key)
     };
 
+    // Deadlines are absolute unix timestamps, 0 means no deadline
+    let now = Clock::get()?.unix_timestamp;
+    let to_deadline = |window: i64| -> Result<i64, ProgramError> {
+        if window == 0 {
+            Ok(0)
+        } else {
+            now.checked_add(window).ok_or(ProgramError::ArithmeticOverflow)
+        }
+    };
+
     let escrow_data = EscrowAccount {
         buyer,
         seller,
@@ -182,139 +301,280 @@ fn create_offer(
         vault_bump,
         mint,
         fee_collector,
+        funding_deadline: to_deadline(funding_window)?,
+        delivery_deadline: to_deadline(delivery_window)?,
+        dispute_window,
+        seller_confirmed_at: 0,
+        dispute_raised_by: Pubkey::default(),
+        disputed_at: 0,
+        arbiter_opt_in: match (arbiter_opt_in, role) {
+            (false, _) => 0,
+            (true, 0) => ARBITER_OPT_IN_BUYER,
+            (true, _) => ARBITER_OPT_IN_SELLER,
+        },
+        ruling_buyer_amount: 0,
+        ruling_seller_amount: 0,
+        milestone_count: 0,
+        released_amount: 0,
+        settlement_fee_bps,
+        arbiter_fee_mode,
+        arbiter_fee,
+        arbiter_fee_paid: 0,
+        funded_amount: 0,
+        rent_payer: *payer.key,
+        held_fee,
+        seller_bond,
+        funded_bond: 0,
     };
 
     // Create vault account if not exists
     if vault.lamports() == 0 {
         AccountHelper::create_pda_account(
-            initiator,
+            payer,
             vault,
             system_program,
             program_id,
-            &[b"vault", escrow_account.key.as_ref(), &[vault_bump]],
+            &[VAULT_SEED, escrow_account.key.as_ref(), &[vault_bump]],
             0,
             vault_rent,
         )?;
     }
 
+    if held_fee > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, vault.key, held_fee),
+            &[payer.clone(), vault.clone(), system_program.clone()],
+        )?;
+        msg!("Service fee {} lamports held in vault until the offer is joined", held_fee);
+    }
+
     escrow_data.save_to_account(escrow_account)?;
     
     msg!("Offer created successfully");
     msg!("Arbiter: {}", arbiter);
     msg!("Role: {}", if role == 0 { "buyer" } else { "seller" });
     msg!("Amount: {} lamports", amount);
-    msg!("State: Created");
+    msg!("Funding deadline: {}", escrow_data.funding_deadline);
+    msg!("Delivery deadline: {}", escrow_data.delivery_deadline);
+    msg!("Dispute window: {} seconds", escrow_data.dispute_window);
+    msg!("Arbiter fee: {} (mode {})", escrow_data.arbiter_fee, escrow_data.arbiter_fee_mode);
+    msg!("Seller bond: {}", escrow_data.seller_bond);
+    EscrowEvent::OfferCreated {
+        escrow: *escrow_account.key,
+        initiator: *initiator.key,
+        role,
+        amount,
+        mint,
+        arbiter,
+    }
+    .emit();
     
     Ok(())
 }
 
-/// Allows the second party to join an existing escrow offer
+/// Allows the second party to join an existing escrow offer.
+/// Any service fee held back for a possible `CancelOffer` is released to the fee collector.
+/// Rejected once the funding deadline has passed.
+///
+/// # Accounts
+/// * `[signer]` joiner - Party joining the offer
+/// * `[writable]` escrow_account - Escrow data account
+/// * `[writable]` vault - Vault PDA (optional, only when a service fee is held)
+/// * `[writable]` fee_collector - Fee collector recorded in the escrow (optional, only when a service fee is held)
+///
+/// # Instruction Data
+/// * byte 0: instruction type (1)
+/// * byte 1: role (0 = joins as buyer, 1 = joins as seller)
+/// * bytes 2-33: joiner pubkey (32 bytes)
+/// * byte 34: joiner lets the arbiter rule without a dispute (optional, 0 = no, 1 = yes)
 fn join_offer(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    instruction_data: &[u8],
+    role: u8,
+    joiner: Pubkey,
+    arbiter_opt_in: bool,
 ) -> ProgramResult {
-    ValidationHelper::validate_instruction_data_length(instruction_data, 34, "JoinOffer")?;
-    
-    let role = instruction_data[1];
-    let joiner = Pubkey::new_from_array(instruction_data[2..34].try_into().unwrap());
-
     let accounts_iter = &mut accounts.iter();
     let joiner_acc = next_account_info(accounts_iter)?;
     let escrow_account = next_account_info(accounts_iter)?;
+    let vault = next_account_info(accounts_iter).ok();
+    let fee_collector_account = next_account_info(accounts_iter).ok();
 
     // Validations
+    ValidationHelper::validate_role(role)?;
     ValidationHelper::validate_signer(joiner_acc, "Joiner")?;
-    ValidationHelper::validate_account_key(joiner_acc, &joiner, "Joiner")?;
+    ValidationHelper::validate_account_key(joiner_acc, &joiner, "Joiner", EscrowError::InvalidParty)?;
     ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
 
     let mut escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
-    
-    if escrow_data.get_state()? != EscrowState::Created {
-        msg!("Offer must be in Created state");
-        return Err(ProgramError::InvalidAccountData);
-    }
+    let new_state = next_state(&escrow_data, Action::JoinOffer, Actor::of(&escrow_data, joiner_acc.key))?;
 
     // Set the missing role
     if role == 0 {
         if escrow_data.buyer != Pubkey::default() {
             msg!("Buyer already set");
-            return Err(ProgramError::AccountAlreadyInitialized);
+            return Err(EscrowError::AccountAlreadySet.into());
         }
         escrow_data.buyer = joiner;
     } else {
         if escrow_data.seller != Pubkey::default() {
             msg!("Seller already set");
-            return Err(ProgramError::AccountAlreadyInitialized);
+            return Err(EscrowError::AccountAlreadySet.into());
         }
         escrow_data.seller = joiner;
     }
 
-    escrow_data.set_state(EscrowState::Initialized);
+    if arbiter_opt_in {
+        escrow_data.arbiter_opt_in |= if role == 0 { ARBITER_OPT_IN_BUYER } else { ARBITER_OPT_IN_SELLER };
+    }
+
+    if escrow_data.held_fee > 0 {
+        let vault = vault.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let fee_collector_account = fee_collector_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
+        ValidationHelper::validate_fee_collector(fee_collector_account, &escrow_data.fee_collector)?;
+
+        TokenTransfer::transfer_sol(vault, fee_collector_account, escrow_data.held_fee)?;
+        msg!("Held service fee {} lamports released to {}", escrow_data.held_fee, escrow_data.fee_collector);
+        escrow_data.held_fee = 0;
+    }
+
+    escrow_data.set_state(new_state);
     escrow_data.save_to_account(escrow_account)?;
     
     msg!("Offer joined by {}: {}", if role == 0 { "buyer" } else { "seller" }, joiner);
-    msg!("State: Initialized");
+    EscrowEvent::Joined { escrow: *escrow_account.key, joiner, role }.emit();
     
     Ok(())
 }
 
-/// Allows the buyer to fund the escrow with the agreed amount
+/// Allows the buyer to fund the escrow with the agreed amount.
+/// Offers with a seller bond can only be funded once the seller ran FundCollateral.
+///
+/// # Accounts
+/// * `[signer, writable]` buyer - Buyer (pays the amount and the vault token account rent)
+/// * `[writable]` escrow_account - Escrow data account
+/// * `[writable]` vault - Vault PDA (receives SOL)
+/// * `[]` system_program - System program
+/// * `[]` mint - SPL token mint (optional, SPL only)
+/// * `[writable]` buyer_token_account - Buyer token account (optional, SPL only)
+/// * `[writable]` vault_token_account - Vault PDA's associated token account, created if missing (optional, SPL only)
+/// * `[]` token_program - SPL Token or Token-2022 program (optional, SPL only)
+/// * `[]` associated_token_program - Associated Token Account program (optional, SPL only)
 fn fund_escrow(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
     let buyer = next_account_info(accounts_iter)?;
     let escrow_account = next_account_info(accounts_iter)?;
     let vault = next_account_info(accounts_iter)?;
     let system_program = next_account_info(accounts_iter)?;
-    let _mint_account = next_account_info(accounts_iter).ok();
+    let mint_account = next_account_info(accounts_iter).ok();
     let buyer_token_account = next_account_info(accounts_iter).ok();
     let vault_token_account = next_account_info(accounts_iter).ok();
     let token_program = next_account_info(accounts_iter).ok();
+    let associated_token_program = next_account_info(accounts_iter).ok();
 
     // Validations
     ValidationHelper::validate_signer(buyer, "Buyer")?;
     ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
 
     let mut escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
-    
-    if escrow_data.get_state()? != EscrowState::Initialized {
-        msg!("Escrow must be in Initialized state");
-        return Err(ProgramError::InvalidAccountData);
-    }
+    let new_state = next_state(&escrow_data, Action::FundEscrow, Actor::of(&escrow_data, buyer.key))?;
 
     ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
-    ValidationHelper::validate_participant(&escrow_data, buyer.key, "buyer")?;
 
-    if TokenTransfer::is_native_mint(&escrow_data.mint) {
-        ValidationHelper::validate_system_program(system_program)?;
-        invoke(
-            &system_instruction::transfer(buyer.key, vault.key, escrow_data.amount),
-            &[buyer.clone(), vault.clone(), system_program.clone()],
-        )?;
-    } else {
-        let buyer_token_account = buyer_token_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
-        let vault_token_account = vault_token_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
-        let token_program = token_program.ok_or(ProgramError::NotEnoughAccountKeys)?;
-        
-        TokenTransfer::transfer_spl_token(
-            buyer_token_account,
-            vault_token_account,
-            buyer,
-            token_program,
-            escrow_data.amount,
-            None,
-        )?;
+    let deposit = VaultDeposit {
+        mint: &escrow_data.mint,
+        depositor: buyer,
+        vault,
+        system_program,
+        mint_account,
+        depositor_token_account: buyer_token_account,
+        vault_token_account,
+        token_program,
+        associated_token_program,
+    };
+    let funded_amount = TokenTransfer::deposit_into_vault(&deposit, escrow_data.amount)?;
+
+    // Milestone amounts are fixed up front, so they need the full amount in the vault
+    if escrow_data.is_milestone_escrow() && funded_amount != escrow_data.amount {
+        msg!("Milestone escrows require the full amount in the vault, received {}", funded_amount);
+        return Err(EscrowError::InsufficientFunds.into());
     }
 
-    escrow_data.set_state(EscrowState::Funded);
+    escrow_data.funded_amount = funded_amount;
+    escrow_data.set_state(new_state);
     escrow_data.save_to_account(escrow_account)?;
     
-    msg!("Escrow funded successfully. Amount: {} lamports", escrow_data.amount);
-    msg!("State: Funded");
+    msg!("Escrow funded successfully. Amount: {}, received: {}", escrow_data.amount, funded_amount);
+    EscrowEvent::Funded { escrow: *escrow_account.key, buyer: *buyer.key, amount: funded_amount }.emit();
     
     Ok(())
 }
 
+/// Seller deposits the bond set in `create_offer`. Required before the buyer may fund
+/// when the offer has a seller bond.
+///
+/// # Accounts
+/// * `[signer, writable]` seller - Seller (pays the bond and, for SPL escrows, the vault token account rent)
+/// * `[writable]` escrow_account - Escrow data account
+/// * `[writable]` vault - Vault PDA (receives SOL)
+/// * `[]` system_program - System program
+/// * `[]` mint - SPL token mint (optional, SPL only)
+/// * `[writable]` seller_token_account - Seller token account (optional, SPL only)
+/// * `[writable]` vault_token_account - Vault PDA's associated token account, created if missing (optional, SPL only)
+/// * `[]` token_program - SPL Token or Token-2022 program (optional, SPL only)
+/// * `[]` associated_token_program - Associated Token Account program (optional, SPL only)
+///
+/// # Instruction Data
+/// * byte 0: instruction type (22)
+fn fund_collateral(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let seller = next_account_info(accounts_iter)?;
+    let escrow_account = next_account_info(accounts_iter)?;
+    let vault = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let mint_account = next_account_info(accounts_iter).ok();
+    let seller_token_account = next_account_info(accounts_iter).ok();
+    let vault_token_account = next_account_info(accounts_iter).ok();
+    let token_program = next_account_info(accounts_iter).ok();
+    let associated_token_program = next_account_info(accounts_iter).ok();
+
+    // Validations
+    ValidationHelper::validate_signer(seller, "Seller")?;
+    ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
+
+    let mut escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
+    let new_state = next_state(&escrow_data, Action::FundCollateral, Actor::of(&escrow_data, seller.key))?;
+
+    ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
+
+    let deposit = VaultDeposit {
+        mint: &escrow_data.mint,
+        depositor: seller,
+        vault,
+        system_program,
+        mint_account,
+        depositor_token_account: seller_token_account,
+        vault_token_account,
+        token_program,
+        associated_token_program,
+    };
+    let funded_bond = TokenTransfer::deposit_into_vault(&deposit, escrow_data.seller_bond)?;
+    if funded_bond == 0 {
+        msg!("No collateral arrived in the vault");
+        return Err(EscrowError::InsufficientFunds.into());
+    }
+
+    escrow_data.funded_bond = funded_bond;
+    escrow_data.set_state(new_state);
+    escrow_data.save_to_account(escrow_account)?;
+
+    msg!("Seller collateral deposited. Bond: {}, received: {}", escrow_data.seller_bond, funded_bond);
+    EscrowEvent::CollateralFunded { escrow: *escrow_account.key, seller: *seller.key, amount: funded_bond }.emit();
+
+    Ok(())
+}
+
 /// Allows the seller to confirm they have fulfilled their obligations
 fn seller_confirm(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
@@ -326,24 +586,20 @@ fn seller_confirm(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResul
     ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
     
     let mut escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
+    let new_state = next_state(&escrow_data, Action::SellerConfirm, Actor::of(&escrow_data, seller.key))?;
     
-    if escrow_data.get_state()? != EscrowState::Funded {
-        msg!("Escrow must be in Funded state");
-        return Err(ProgramError::InvalidAccountData);
-    }
-    
-    ValidationHelper::validate_participant(&escrow_data, seller.key, "seller")?;
-    
-    escrow_data.set_state(EscrowState::SellerConfirmed);
+    escrow_data.seller_confirmed_at = Clock::get()?.unix_timestamp;
+    escrow_data.set_state(new_state);
     escrow_data.save_to_account(escrow_account)?;
     
     msg!("Seller confirmed fulfillment");
-    msg!("State: SellerConfirmed");
+    EscrowEvent::SellerConfirmed { escrow: *escrow_account.key, seller: *seller.key, milestone: None }.emit();
     
     Ok(())
 }
 
-/// Allows the buyer to confirm and release funds to seller
+/// Allows the buyer to confirm and release funds to seller.
+/// If the escrow charges a settlement fee, the fee destination is passed as the last account.
 fn confirm_escrow(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
     let buyer = next_account_info(accounts_iter)?;
@@ -351,7 +607,7 @@ fn confirm_escrow(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResul
     let vault = next_account_info(accounts_iter)?;
     let _system_program = next_account_info(accounts_iter)?;
     let seller_account = next_account_info(accounts_iter)?;
-    let _mint_account = next_account_info(accounts_iter).ok();
+    let mint_account = next_account_info(accounts_iter).ok();
     let vault_token_account = next_account_info(accounts_iter).ok();
     let seller_token_account = next_account_info(accounts_iter).ok();
     let token_program = next_account_info(accounts_iter).ok();
@@ -361,290 +617,1371 @@ fn confirm_escrow(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResul
     ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
     
     let mut escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
-    
-    if escrow_data.get_state()? != EscrowState::SellerConfirmed {
-        msg!("Escrow must be in SellerConfirmed state");
-        return Err(ProgramError::InvalidAccountData);
-    }
+    let new_state = next_state(&escrow_data, Action::ConfirmEscrow, Actor::of(&escrow_data, buyer.key))?;
     
     ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
-    ValidationHelper::validate_participant(&escrow_data, buyer.key, "buyer")?;
-    ValidationHelper::validate_account_key(seller_account, &escrow_data.seller, "seller")?;
-
-    // Transfer funds to seller
-    if TokenTransfer::is_native_mint(&escrow_data.mint) {
-        TokenTransfer::transfer_sol(vault, seller_account, escrow_data.amount)?;
-    } else {
-        let vault_token_account = vault_token_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
-        let seller_token_account = seller_token_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
-        let token_program = token_program.ok_or(ProgramError::NotEnoughAccountKeys)?;
-        
-        TokenTransfer::transfer_spl_token(
-            vault_token_account,
-            seller_token_account,
-            vault,
-            token_program,
-            escrow_data.amount,
-            Some(&[b"vault", escrow_account.key.as_ref(), &[escrow_data.vault_bump]]),
-        )?;
-    }
+    ValidationHelper::validate_account_key(seller_account, &escrow_data.seller, "seller", EscrowError::NotSeller)?;
+
+    // Transfer funds to seller, minus the settlement fee
+    let payout = VaultPayout {
+        mint: &escrow_data.mint,
+        mint_account,
+        vault,
+        vault_token_account,
+        token_program,
+        vault_seeds: &[VAULT_SEED, escrow_account.key.as_ref(), &[escrow_data.vault_bump]],
+    };
+    let amount = escrow_data.remaining_amount()?;
+    let fee = pay_seller(&escrow_data, &payout, seller_account, seller_token_account, accounts.last(), amount)?;
+    release_collateral(escrow_account.key, &escrow_data, &payout, seller_account, seller_token_account, true)?;
 
-    escrow_data.set_state(EscrowState::Completed);
+    escrow_data.set_state(new_state);
     escrow_data.save_to_account(escrow_account)?;
     
     msg!("Escrow confirmed by buyer. Funds released to seller");
-    msg!("State: Completed");
+    EscrowEvent::Released {
+        escrow: *escrow_account.key,
+        seller: *seller_account.key,
+        amount: amount - fee,
+        fee,
+        arbiter_fee: 0,
+        milestone: None,
+    }
+    .emit();
     
     Ok(())
 }
 
-/// Arbiter confirms escrow, funds go to seller
+/// Arbiter confirms escrow, funds go to seller minus the arbiter fee.
+/// For SPL escrows with an arbiter fee, the arbiter token account follows `token_program`.
+/// If the escrow charges a settlement fee, the fee destination is passed as the last account.
 fn arbiter_confirm(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
     let arbiter = next_account_info(accounts_iter)?;
     let escrow_account = next_account_info(accounts_iter)?;
     let vault = next_account_info(accounts_iter)?;
     let seller = next_account_info(accounts_iter)?;
-    let _mint_account = next_account_info(accounts_iter).ok();
+    let mint_account = next_account_info(accounts_iter).ok();
     let vault_token_account = next_account_info(accounts_iter).ok();
     let seller_token_account = next_account_info(accounts_iter).ok();
     let token_program = next_account_info(accounts_iter).ok();
+    let arbiter_token_account = next_account_info(accounts_iter).ok();
 
     // Validations
     ValidationHelper::validate_signer(arbiter, "Arbiter")?;
     ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
 
     let mut escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
+    let new_state = next_state(&escrow_data, Action::ArbiterConfirm, Actor::of(&escrow_data, arbiter.key))?;
 
     ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
-    ValidationHelper::validate_participant(&escrow_data, arbiter.key, "arbiter")?;
-    ValidationHelper::validate_account_key(seller, &escrow_data.seller, "seller")?;
-
-    let state = escrow_data.get_state()?;
-    if state != EscrowState::Funded && state != EscrowState::SellerConfirmed {
-        msg!("Escrow must be in Funded or SellerConfirmed state");
-        return Err(ProgramError::InvalidAccountData);
-    }
-
-    // Transfer funds to seller
-    if TokenTransfer::is_native_mint(&escrow_data.mint) {
-        TokenTransfer::transfer_sol(vault, seller, escrow_data.amount)?;
-    } else {
-        let vault_token_account = vault_token_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
-        let seller_token_account = seller_token_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
-        let token_program = token_program.ok_or(ProgramError::NotEnoughAccountKeys)?;
-        
-        TokenTransfer::transfer_spl_token(
-            vault_token_account,
-            seller_token_account,
-            vault,
-            token_program,
-            escrow_data.amount,
-            Some(&[b"vault", escrow_account.key.as_ref(), &[escrow_data.vault_bump]]),
-        )?;
-    }
+    ValidationHelper::validate_account_key(seller, &escrow_data.seller, "seller", EscrowError::NotSeller)?;
+
+    // Pay the arbiter, then transfer the rest to seller minus the settlement fee
+    let remaining = escrow_data.remaining_amount()?;
+    let mint = escrow_data.mint;
+    let payout = VaultPayout {
+        mint: &mint,
+        mint_account,
+        vault,
+        vault_token_account,
+        token_program,
+        vault_seeds: &[VAULT_SEED, escrow_account.key.as_ref(), &[escrow_data.vault_bump]],
+    };
+    let arbiter_fee = pay_arbiter_fee(&mut escrow_data, &payout, arbiter, arbiter_token_account, remaining)?;
+    let amount = remaining - arbiter_fee;
+    let fee = pay_seller(&escrow_data, &payout, seller, seller_token_account, accounts.last(), amount)?;
+    release_collateral(escrow_account.key, &escrow_data, &payout, seller, seller_token_account, true)?;
 
-    escrow_data.set_state(EscrowState::Completed);
+    escrow_data.set_state(new_state);
     escrow_data.save_to_account(escrow_account)?;
     
     msg!("Escrow completed by arbiter. Funds released to seller");
-    msg!("State: Completed");
+    EscrowEvent::Released {
+        escrow: *escrow_account.key,
+        seller: *seller.key,
+        amount: amount - fee,
+        fee,
+        arbiter_fee,
+        milestone: None,
+    }
+    .emit();
     
     Ok(())
 }
 
-/// Arbiter cancels escrow, funds return to buyer
+/// Arbiter cancels escrow, funds return to buyer minus the arbiter fee.
+/// For SPL escrows with an arbiter fee, the arbiter token account follows `token_program`.
 fn arbiter_cancel(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
     let arbiter = next_account_info(accounts_iter)?;
     let escrow_account = next_account_info(accounts_iter)?;
     let vault = next_account_info(accounts_iter)?;
     let buyer = next_account_info(accounts_iter)?;
-    let _mint_account = next_account_info(accounts_iter).ok();
+    let mint_account = next_account_info(accounts_iter).ok();
     let vault_token_account = next_account_info(accounts_iter).ok();
     let buyer_token_account = next_account_info(accounts_iter).ok();
     let token_program = next_account_info(accounts_iter).ok();
+    let arbiter_token_account = next_account_info(accounts_iter).ok();
 
     // Validations
     ValidationHelper::validate_signer(arbiter, "Arbiter")?;
     ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
 
     let mut escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
+    let new_state = next_state(&escrow_data, Action::ArbiterCancel, Actor::of(&escrow_data, arbiter.key))?;
 
     ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
-    ValidationHelper::validate_participant(&escrow_data, arbiter.key, "arbiter")?;
-    ValidationHelper::validate_account_key(buyer, &escrow_data.buyer, "buyer")?;
-
-    let state = escrow_data.get_state()?;
-    if state != EscrowState::Funded && state != EscrowState::SellerConfirmed {
-        msg!("Escrow must be in Funded or SellerConfirmed state");
-        return Err(ProgramError::InvalidAccountData);
-    }
-
-    // Return funds to buyer
-    if TokenTransfer::is_native_mint(&escrow_data.mint) {
-        TokenTransfer::transfer_sol(vault, buyer, escrow_data.amount)?;
-    } else {
-        let vault_token_account = vault_token_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
-        let buyer_token_account = buyer_token_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
-        let token_program = token_program.ok_or(ProgramError::NotEnoughAccountKeys)?;
-        
-        TokenTransfer::transfer_spl_token(
-            vault_token_account,
-            buyer_token_account,
-            vault,
-            token_program,
-            escrow_data.amount,
-            Some(&[b"vault", escrow_account.key.as_ref(), &[escrow_data.vault_bump]]),
-        )?;
-    }
+    ValidationHelper::validate_account_key(buyer, &escrow_data.buyer, "buyer", EscrowError::NotBuyer)?;
+
+    // Pay the arbiter, then return the rest to buyer
+    let remaining = escrow_data.remaining_amount()?;
+    let mint = escrow_data.mint;
+    let payout = VaultPayout {
+        mint: &mint,
+        mint_account,
+        vault,
+        vault_token_account,
+        token_program,
+        vault_seeds: &[VAULT_SEED, escrow_account.key.as_ref(), &[escrow_data.vault_bump]],
+    };
+    let arbiter_fee = pay_arbiter_fee(&mut escrow_data, &payout, arbiter, arbiter_token_account, remaining)?;
+    TokenTransfer::payout_from_vault(&payout, buyer, buyer_token_account, remaining - arbiter_fee)?;
+    release_collateral(escrow_account.key, &escrow_data, &payout, buyer, buyer_token_account, false)?;
 
-    escrow_data.set_state(EscrowState::Cancelled);
+    escrow_data.set_state(new_state);
     escrow_data.save_to_account(escrow_account)?;
     
     msg!("Escrow cancelled by arbiter. Funds returned to buyer");
-    msg!("State: Cancelled");
+    EscrowEvent::Refunded {
+        escrow: *escrow_account.key,
+        buyer: *buyer.key,
+        amount: remaining - arbiter_fee,
+        arbiter_fee,
+        milestone: None,
+    }
+    .emit();
     
     Ok(())
 }
 
-/// Buyer and seller mutually cancel escrow
-fn mutual_cancel(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+/// Arbiter splits the vault between seller and buyer
+///
+/// # Accounts
+/// * `[signer, writable]` arbiter - Escrow arbiter (receives SOL arbiter fee)
+/// * `[writable]` escrow_account - Escrow data account
+/// * `[writable]` vault - Vault PDA
+/// * `[writable]` buyer - Buyer account (receives SOL share)
+/// * `[writable]` seller - Seller account (receives SOL share)
+/// * `[]` mint - SPL token mint (optional, SPL only)
+/// * `[writable]` vault_token_account - Vault PDA's associated token account (optional, SPL only)
+/// * `[writable]` buyer_token_account - Buyer token account (optional, SPL only)
+/// * `[writable]` seller_token_account - Seller token account (optional, SPL only)
+/// * `[]` token_program - SPL Token or Token-2022 program (optional, SPL only)
+/// * `[writable]` arbiter_token_account - Arbiter token account (optional, SPL only, when an arbiter fee is due)
+/// * `[writable]` fee_destination - Fee collector (SOL) or its token account (SPL), last account, only when a settlement fee is due
+///
+/// The arbiter fee is deducted first; the split applies to what is left.
+/// A seller bond held in the vault is split in the same proportion.
+///
+/// # Instruction Data
+/// * byte 0: instruction type (13)
+/// * byte 1: mode (0 = seller share in basis points, 1 = explicit seller amount)
+/// * bytes 2-9: seller share (u64, little-endian); the buyer gets the remainder
+fn arbiter_settle(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    mode: u8,
+    seller_share: u64,
+) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
-    let buyer = next_account_info(accounts_iter)?;
-    let seller = next_account_info(accounts_iter)?;
+    let arbiter = next_account_info(accounts_iter)?;
     let escrow_account = next_account_info(accounts_iter)?;
     let vault = next_account_info(accounts_iter)?;
-    let _mint_account = next_account_info(accounts_iter).ok();
+    let buyer = next_account_info(accounts_iter)?;
+    let seller = next_account_info(accounts_iter)?;
+    let mint_account = next_account_info(accounts_iter).ok();
     let vault_token_account = next_account_info(accounts_iter).ok();
     let buyer_token_account = next_account_info(accounts_iter).ok();
+    let seller_token_account = next_account_info(accounts_iter).ok();
     let token_program = next_account_info(accounts_iter).ok();
+    let arbiter_token_account = next_account_info(accounts_iter).ok();
 
-    // Both parties must sign
-    if !buyer.is_signer || !seller.is_signer {
-        msg!("Both buyer and seller must sign");
-        return Err(ProgramError::MissingRequiredSignature);
-    }
-    
+    // Validations
+    ValidationHelper::validate_signer(arbiter, "Arbiter")?;
     ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
 
     let mut escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
 
-    ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
-    ValidationHelper::validate_account_key(buyer, &escrow_data.buyer, "buyer")?;
-    ValidationHelper::validate_account_key(seller, &escrow_data.seller, "seller")?;
-
-    let state = escrow_data.get_state()?;
-    if state != EscrowState::Initialized && state != EscrowState::Funded {
-        msg!("Escrow can only be cancelled in Initialized or Funded state");
-        return Err(ProgramError::InvalidAccountData);
-    }
+    // The arbiter fee comes off the top, the parties split the rest
+    let remaining = escrow_data.remaining_amount()?;
+    let arbiter_fee = escrow_data.arbiter_fee_due(remaining)?;
+    let distributable = remaining - arbiter_fee;
+    // The seller bond is split in the same proportion
+    let bond = escrow_data.held_collateral()?;
+    let (seller_amount, buyer_amount, seller_bond) = match mode {
+        0 => {
+            let bps = u16::try_from(seller_share).map_err(|_| EscrowError::InvalidBasisPoints)?;
+            let (seller_amount, buyer_amount) = AmountHelper::split_basis_points(distributable, bps)?;
+            (seller_amount, buyer_amount, AmountHelper::basis_points_of(bond, bps)?)
+        }
+        1 => {
+            let (seller_amount, buyer_amount) = AmountHelper::split_explicit(distributable, seller_share)?;
+            (seller_amount, buyer_amount, AmountHelper::pro_rata(bond, seller_amount, distributable)?)
+        }
+        _ => {
+            msg!("Invalid settlement mode: {}", mode);
+            return Err(EscrowError::InvalidRuling.into());
+        }
+    };
+    let action = Action::ArbiterSettle { seller_paid: seller_amount > 0 };
+    let new_state = next_state(&escrow_data, action, Actor::of(&escrow_data, arbiter.key))?;
 
-    // If funded, return funds to buyer
-    if state == EscrowState::Funded {
-        if TokenTransfer::is_native_mint(&escrow_data.mint) {
-            TokenTransfer::transfer_sol(vault, buyer, escrow_data.amount)?;
-        } else {
-            let vault_token_account = vault_token_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
-            let buyer_token_account = buyer_token_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
-            let token_program = token_program.ok_or(ProgramError::NotEnoughAccountKeys)?;
-            
-            TokenTransfer::transfer_spl_token(
-                vault_token_account,
-                buyer_token_account,
-                vault,
-                token_program,
-                escrow_data.amount,
-                Some(&[b"vault", escrow_account.key.as_ref(), &[escrow_data.vault_bump]]),
-            )?;
+    ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
+    ValidationHelper::validate_account_key(buyer, &escrow_data.buyer, "buyer", EscrowError::NotBuyer)?;
+    ValidationHelper::validate_account_key(seller, &escrow_data.seller, "seller", EscrowError::NotSeller)?;
+
+    // Pay the arbiter and both shares
+    let mint = escrow_data.mint;
+    let payout = VaultPayout {
+        mint: &mint,
+        mint_account,
+        vault,
+        vault_token_account,
+        token_program,
+        vault_seeds: &[VAULT_SEED, escrow_account.key.as_ref(), &[escrow_data.vault_bump]],
+    };
+    pay_arbiter_fee(&mut escrow_data, &payout, arbiter, arbiter_token_account, remaining)?;
+    let fee = pay_seller(&escrow_data, &payout, seller, seller_token_account, accounts.last(), seller_amount)?;
+    TokenTransfer::payout_from_vault(&payout, buyer, buyer_token_account, buyer_amount)?;
+    if bond > 0 {
+        TokenTransfer::payout_from_vault(&payout, seller, seller_token_account, seller_bond)?;
+        TokenTransfer::payout_from_vault(&payout, buyer, buyer_token_account, bond - seller_bond)?;
+        msg!("Seller collateral split. Seller: {}, Buyer: {}", seller_bond, bond - seller_bond);
+        EscrowEvent::CollateralReleased {
+            escrow: *escrow_account.key,
+            seller_amount: seller_bond,
+            buyer_amount: bond - seller_bond,
         }
+        .emit();
     }
 
-    escrow_data.set_state(EscrowState::Cancelled);
+    escrow_data.ruling_seller_amount = seller_amount;
+    escrow_data.ruling_buyer_amount = buyer_amount;
+    escrow_data.set_state(new_state);
     escrow_data.save_to_account(escrow_account)?;
-    
-    msg!("Escrow mutually cancelled");
-    msg!("State: Cancelled");
-    
+
+    msg!("Escrow settled by arbiter. Seller: {}, Buyer: {}", seller_amount, buyer_amount);
+    EscrowEvent::Settled {
+        escrow: *escrow_account.key,
+        seller_amount: seller_amount - fee,
+        buyer_amount,
+        fee,
+        arbiter_fee,
+    }
+    .emit();
+
     Ok(())
 }
 
-/// Close escrow account, return rent to closer
-fn close_escrow(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+/// Splits a not-yet-joined offer into staged milestones.
+/// Milestone amounts must add up to the escrow amount. Offers with a seller bond cannot use milestones.
+///
+/// # Accounts
+/// * `[signer, writable]` initiator - Party that created the offer
+/// * `[writable]` escrow_account - Escrow data account
+/// * `[writable]` milestone_account - Milestone PDA (`[b"milestones", escrow_key]`)
+/// * `[]` system_program - System program
+///
+/// # Instruction Data
+/// * byte 0: instruction type (14)
+/// * byte 1: milestone count (1..=16)
+/// * bytes 2..: milestone amounts (u64 little-endian each)
+fn init_milestones(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amounts: Vec<u64>,
+) -> ProgramResult {
+    let count = amounts.len();
+    if count == 0 || count > MilestoneAccount::MAX_MILESTONES {
+        msg!("Milestone count must be between 1 and {}", MilestoneAccount::MAX_MILESTONES);
+        return Err(EscrowError::InvalidMilestoneCount.into());
+    }
+
     let accounts_iter = &mut accounts.iter();
-    let closer = next_account_info(accounts_iter)?;
+    let initiator = next_account_info(accounts_iter)?;
     let escrow_account = next_account_info(accounts_iter)?;
-    let vault = next_account_info(accounts_iter).ok();
+    let milestone_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
 
-    ValidationHelper::validate_signer(closer, "Closer")?;
+    // Validations
+    ValidationHelper::validate_signer(initiator, "Initiator")?;
     ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
+    ValidationHelper::validate_system_program(system_program)?;
 
-    let escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
+    let mut escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
 
-    if !escrow_data.can_be_closed()? {
-        msg!("Escrow must be completed or cancelled");
-        return Err(ProgramError::InvalidAccountData);
+    next_state(&escrow_data, Action::InitMilestones, Actor::of(&escrow_data, initiator.key))?;
+    if escrow_data.is_milestone_escrow() {
+        msg!("Milestones already set");
+        return Err(EscrowError::AccountAlreadySet.into());
+    }
+    // Milestone payouts leave no single settlement to return or forfeit a bond at
+    if escrow_data.seller_bond > 0 {
+        msg!("Offers with a seller bond cannot be split into milestones");
+        return Err(EscrowError::CollateralNotSupported.into());
     }
 
-    if !escrow_data.is_participant(closer.key) {
-        msg!("Closer must be participant or arbiter");
-        return Err(ProgramError::IllegalOwner);
+    let mut milestones = Vec::with_capacity(count);
+    let mut total: u64 = 0;
+    for (i, amount) in amounts.into_iter().enumerate() {
+        if amount == 0 {
+            msg!("Milestone {} amount must be greater than zero", i);
+            return Err(EscrowError::InvalidAmount.into());
+        }
+        total = total.checked_add(amount).ok_or(ProgramError::ArithmeticOverflow)?;
+        milestones.push(Milestone {
+            amount,
+            status: MilestoneStatus::Pending as u8,
+        });
+    }
+    if total != escrow_data.amount {
+        msg!("Milestone amounts must sum to {}, got {}", escrow_data.amount, total);
+        return Err(EscrowError::InvalidAmount.into());
     }
 
-    // Close escrow account - return all lamports
-    let escrow_balance = escrow_account.lamports();
-    **escrow_account.try_borrow_mut_lamports()? = 0;
-    **closer.try_borrow_mut_lamports()? = closer
-        .lamports()
-        .checked_add(escrow_balance)
-        .ok_or(ProgramError::ArithmeticOverflow)?;
+    let (milestone_pda, milestone_bump) = pda::find_milestone_address(program_id, escrow_account.key);
+    if milestone_pda != *milestone_account.key {
+        msg!("Invalid milestone PDA");
+        return Err(EscrowError::InvalidMilestoneAccount.into());
+    }
 
-    // Zero out data
-    let mut data = escrow_account.try_borrow_mut_data()?;
-    data.fill(0);
-
-    // Close vault if provided
-    if let Some(vault) = vault {
-        // Validate vault PDA
-        if let Ok(()) = ValidationHelper::validate_vault_pda(
-            vault, 
-            escrow_account.key, 
-            program_id, 
-            escrow_data.vault_bump
-        ) {
-            let vault_balance = vault.lamports();
-            if vault_balance > 0 {
-                **vault.try_borrow_mut_lamports()? = 0;
-                **closer.try_borrow_mut_lamports()? = closer
-                    .lamports()
-                    .checked_add(vault_balance)
-                    .ok_or(ProgramError::ArithmeticOverflow)?;
-            }
-        }
+    let rent = Rent::get()?;
+    AccountHelper::create_pda_account(
+        initiator,
+        milestone_account,
+        system_program,
+        program_id,
+        &[MILESTONE_SEED, escrow_account.key.as_ref(), &[milestone_bump]],
+        MilestoneAccount::LEN as u64,
+        rent.minimum_balance(MilestoneAccount::LEN),
+    )?;
+
+    let milestone_data = MilestoneAccount {
+        escrow: *escrow_account.key,
+        bump: milestone_bump,
+        milestones,
+    };
+    milestone_data.save_to_account(milestone_account)?;
+
+    escrow_data.milestone_count = count as u8;
+    escrow_data.save_to_account(escrow_account)?;
+
+    msg!("Milestones set: {}", count);
+
+    Ok(())
+}
+
+/// Checks `action` by `actor` against the escrow state machine at the current time,
+/// returning the state to store once the action succeeds
+fn next_state(escrow_data: &EscrowAccount, action: Action, actor: Actor) -> Result<EscrowState, ProgramError> {
+    let conditions = Conditions::at(escrow_data, Clock::get()?.unix_timestamp);
+    Ok(transition(escrow_data.get_state()?, action, actor, &conditions)?)
+}
+
+/// Loads the milestone account of an escrow, checking ownership and PDA
+fn load_milestones(
+    program_id: &Pubkey,
+    milestone_account: &AccountInfo,
+    escrow_key: &Pubkey,
+) -> Result<MilestoneAccount, ProgramError> {
+    ValidationHelper::validate_program_account(milestone_account, program_id, "milestone_account")?;
+    let milestone_data = MilestoneAccount::from_account_data(&milestone_account.try_borrow_data()?)?;
+    if milestone_data.escrow != *escrow_key {
+        msg!("Milestone account belongs to another escrow");
+        return Err(EscrowError::InvalidMilestoneAccount.into());
     }
+    ValidationHelper::validate_milestone_pda(milestone_account, escrow_key, program_id, milestone_data.bump)?;
+    Ok(milestone_data)
+}
 
-    msg!("Escrow closed. {} lamports returned", escrow_balance);
-    
+/// Marks the escrow finished once every milestone is released or refunded
+fn finalize_milestones(
+    escrow_data: &mut EscrowAccount,
+    milestone_data: &MilestoneAccount,
+) -> ProgramResult {
+    if milestone_data.all_resolved()? {
+        let action = Action::FinishMilestones { any_released: milestone_data.any_released()? };
+        escrow_data.set_state(next_state(escrow_data, action, Actor::ANYONE)?);
+        msg!("All milestones resolved");
+        msg!("State: {:?}", escrow_data.get_state()?);
+    }
     Ok(())
 }
 
-/// Get escrow information (for debugging)
-fn get_escrow_info(_program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+/// Seller confirms delivery of a single milestone
+///
+/// # Accounts
+/// * `[signer]` seller - Escrow seller
+/// * `[]` escrow_account - Escrow data account
+/// * `[writable]` milestone_account - Milestone PDA
+///
+/// # Instruction Data
+/// * byte 0: instruction type (15)
+/// * byte 1: milestone index
+fn confirm_milestone(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    index: u8,
+) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
+    let seller = next_account_info(accounts_iter)?;
     let escrow_account = next_account_info(accounts_iter)?;
+    let milestone_account = next_account_info(accounts_iter)?;
+
+    // Validations
+    ValidationHelper::validate_signer(seller, "Seller")?;
+    ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
 
     let escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
-    
-    msg!("=== Escrow Information ===");
-    msg!("State: {:?}", escrow_data.get_state()?);
-    msg!("Amount: {} lamports", escrow_data.amount);
-    msg!("Buyer: {}", escrow_data.buyer);
-    msg!("Seller: {}", escrow_data.seller);
-    msg!("Arbiter: {}", escrow_data.arbiter);
-    msg!("Mint: {}", escrow_data.mint);
-    msg!("Fee Collector: {}", escrow_data.fee_collector);
-    msg!("Vault Bump: {}", escrow_data.vault_bump);
-    msg!("==========================");
-    
+    next_state(&escrow_data, Action::ConfirmMilestone, Actor::of(&escrow_data, seller.key))?;
+
+    let mut milestone_data = load_milestones(program_id, milestone_account, escrow_account.key)?;
+    let milestone = milestone_data.get_mut(index)?;
+    if milestone.get_status()? != MilestoneStatus::Pending {
+        msg!("Milestone {} must be Pending", index);
+        return Err(EscrowError::InvalidMilestoneState.into());
+    }
+    milestone.set_status(MilestoneStatus::SellerConfirmed);
+    milestone_data.save_to_account(milestone_account)?;
+
+    msg!("Seller confirmed milestone {}", index);
+    EscrowEvent::SellerConfirmed { escrow: *escrow_account.key, seller: *seller.key, milestone: Some(index) }.emit();
+
+    Ok(())
+}
+
+/// Buyer releases a seller-confirmed milestone, paying its amount to the seller
+///
+/// # Accounts
+/// * `[signer]` buyer - Escrow buyer
+/// * `[writable]` escrow_account - Escrow data account
+/// * `[writable]` vault - Vault PDA
+/// * `[writable]` milestone_account - Milestone PDA
+/// * `[writable]` seller - Seller account (receives SOL)
+/// * `[]` mint - SPL token mint (optional, SPL only)
+/// * `[writable]` vault_token_account - Vault PDA's associated token account (optional, SPL only)
+/// * `[writable]` seller_token_account - Seller token account (optional, SPL only)
+/// * `[]` token_program - SPL Token or Token-2022 program (optional, SPL only)
+/// * `[writable]` fee_destination - Fee collector (SOL) or its token account (SPL), last account, only when a settlement fee is due
+///
+/// # Instruction Data
+/// * byte 0: instruction type (16)
+/// * byte 1: milestone index
+fn release_milestone(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    index: u8,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let buyer = next_account_info(accounts_iter)?;
+    let escrow_account = next_account_info(accounts_iter)?;
+    let vault = next_account_info(accounts_iter)?;
+    let milestone_account = next_account_info(accounts_iter)?;
+    let seller = next_account_info(accounts_iter)?;
+    let mint_account = next_account_info(accounts_iter).ok();
+    let vault_token_account = next_account_info(accounts_iter).ok();
+    let seller_token_account = next_account_info(accounts_iter).ok();
+    let token_program = next_account_info(accounts_iter).ok();
+
+    // Validations
+    ValidationHelper::validate_signer(buyer, "Buyer")?;
+    ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
+
+    let mut escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
+    next_state(&escrow_data, Action::ReleaseMilestone, Actor::of(&escrow_data, buyer.key))?;
+
+    ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
+    ValidationHelper::validate_account_key(seller, &escrow_data.seller, "seller", EscrowError::NotSeller)?;
+
+    let mut milestone_data = load_milestones(program_id, milestone_account, escrow_account.key)?;
+    let milestone = milestone_data.get_mut(index)?;
+    if milestone.get_status()? != MilestoneStatus::SellerConfirmed {
+        msg!("Milestone {} must be confirmed by seller", index);
+        return Err(EscrowError::InvalidMilestoneState.into());
+    }
+    let amount = milestone.amount;
+    milestone.set_status(MilestoneStatus::Released);
+
+    let payout = VaultPayout {
+        mint: &escrow_data.mint,
+        mint_account,
+        vault,
+        vault_token_account,
+        token_program,
+        vault_seeds: &[VAULT_SEED, escrow_account.key.as_ref(), &[escrow_data.vault_bump]],
+    };
+    let fee = pay_seller(&escrow_data, &payout, seller, seller_token_account, accounts.last(), amount)?;
+
+    escrow_data.released_amount = escrow_data
+        .released_amount
+        .checked_add(amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    finalize_milestones(&mut escrow_data, &milestone_data)?;
+
+    milestone_data.save_to_account(milestone_account)?;
+    escrow_data.save_to_account(escrow_account)?;
+
+    msg!("Milestone {} released to seller. Amount: {}", index, amount);
+    EscrowEvent::Released {
+        escrow: *escrow_account.key,
+        seller: *seller.key,
+        amount: amount - fee,
+        fee,
+        arbiter_fee: 0,
+        milestone: Some(index),
+    }
+    .emit();
+
+    Ok(())
+}
+
+/// Arbiter rules on a single milestone, paying it to the seller or refunding the buyer
+///
+/// # Accounts
+/// * `[signer, writable]` arbiter - Escrow arbiter (receives SOL arbiter fee)
+/// * `[writable]` escrow_account - Escrow data account
+/// * `[writable]` vault - Vault PDA
+/// * `[writable]` milestone_account - Milestone PDA
+/// * `[writable]` buyer - Buyer account (receives SOL refund)
+/// * `[writable]` seller - Seller account (receives SOL)
+/// * `[]` mint - SPL token mint (optional, SPL only)
+/// * `[writable]` vault_token_account - Vault PDA's associated token account (optional, SPL only)
+/// * `[writable]` buyer_token_account - Buyer token account (optional, SPL only)
+/// * `[writable]` seller_token_account - Seller token account (optional, SPL only)
+/// * `[]` token_program - SPL Token or Token-2022 program (optional, SPL only)
+/// * `[writable]` arbiter_token_account - Arbiter token account (optional, SPL only, when an arbiter fee is due)
+/// * `[writable]` fee_destination - Fee collector (SOL) or its token account (SPL), last account, only when a settlement fee is due
+///
+/// # Instruction Data
+/// * byte 0: instruction type (17)
+/// * byte 1: milestone index
+/// * byte 2: ruling (0 = release to seller, 1 = refund to buyer)
+fn arbiter_rule_milestone(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    index: u8,
+    ruling: u8,
+) -> ProgramResult {
+    let ruling = match ruling {
+        0 => MilestoneStatus::Released,
+        1 => MilestoneStatus::Refunded,
+        other => {
+            msg!("Invalid milestone ruling: {}", other);
+            return Err(EscrowError::InvalidRuling.into());
+        }
+    };
+
+    let accounts_iter = &mut accounts.iter();
+    let arbiter = next_account_info(accounts_iter)?;
+    let escrow_account = next_account_info(accounts_iter)?;
+    let vault = next_account_info(accounts_iter)?;
+    let milestone_account = next_account_info(accounts_iter)?;
+    let buyer = next_account_info(accounts_iter)?;
+    let seller = next_account_info(accounts_iter)?;
+    let mint_account = next_account_info(accounts_iter).ok();
+    let vault_token_account = next_account_info(accounts_iter).ok();
+    let buyer_token_account = next_account_info(accounts_iter).ok();
+    let seller_token_account = next_account_info(accounts_iter).ok();
+    let token_program = next_account_info(accounts_iter).ok();
+    let arbiter_token_account = next_account_info(accounts_iter).ok();
+
+    // Validations
+    ValidationHelper::validate_signer(arbiter, "Arbiter")?;
+    ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
+
+    let mut escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
+    next_state(&escrow_data, Action::ArbiterRuleMilestone, Actor::of(&escrow_data, arbiter.key))?;
+
+    ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
+    ValidationHelper::validate_account_key(buyer, &escrow_data.buyer, "buyer", EscrowError::NotBuyer)?;
+    ValidationHelper::validate_account_key(seller, &escrow_data.seller, "seller", EscrowError::NotSeller)?;
+
+    let mut milestone_data = load_milestones(program_id, milestone_account, escrow_account.key)?;
+    let milestone = milestone_data.get_mut(index)?;
+    if milestone.get_status()?.is_resolved() {
+        msg!("Milestone {} already resolved", index);
+        return Err(EscrowError::InvalidMilestoneState.into());
+    }
+    let amount = milestone.amount;
+    milestone.set_status(ruling);
+
+    let mint = escrow_data.mint;
+    let payout = VaultPayout {
+        mint: &mint,
+        mint_account,
+        vault,
+        vault_token_account,
+        token_program,
+        vault_seeds: &[VAULT_SEED, escrow_account.key.as_ref(), &[escrow_data.vault_bump]],
+    };
+    let arbiter_fee = pay_arbiter_fee(&mut escrow_data, &payout, arbiter, arbiter_token_account, amount)?;
+    let event = if ruling == MilestoneStatus::Released {
+        let fee = pay_seller(&escrow_data, &payout, seller, seller_token_account, accounts.last(), amount - arbiter_fee)?;
+        EscrowEvent::Released {
+            escrow: *escrow_account.key,
+            seller: *seller.key,
+            amount: amount - arbiter_fee - fee,
+            fee,
+            arbiter_fee,
+            milestone: Some(index),
+        }
+    } else {
+        TokenTransfer::payout_from_vault(&payout, buyer, buyer_token_account, amount - arbiter_fee)?;
+        EscrowEvent::Refunded {
+            escrow: *escrow_account.key,
+            buyer: *buyer.key,
+            amount: amount - arbiter_fee,
+            arbiter_fee,
+            milestone: Some(index),
+        }
+    };
+
+    escrow_data.released_amount = escrow_data
+        .released_amount
+        .checked_add(amount)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    finalize_milestones(&mut escrow_data, &milestone_data)?;
+
+    milestone_data.save_to_account(milestone_account)?;
+    escrow_data.save_to_account(escrow_account)?;
+
+    msg!("Arbiter ruled milestone {}: {:?}. Amount: {}", index, ruling, amount);
+    event.emit();
+
+    Ok(())
+}
+
+/// Pays the arbiter's compensation for ruling over `ruled_amount` out of the vault and
+/// records it on the escrow. Returns the fee paid, which the caller deducts from the
+/// amount handed to the parties.
+fn pay_arbiter_fee<'a>(
+    escrow_data: &mut EscrowAccount,
+    payout: &VaultPayout<'a, '_>,
+    arbiter: &AccountInfo<'a>,
+    arbiter_token_account: Option<&AccountInfo<'a>>,
+    ruled_amount: u64,
+) -> Result<u64, ProgramError> {
+    let fee = escrow_data.arbiter_fee_due(ruled_amount)?;
+    if fee == 0 {
+        return Ok(0);
+    }
+
+    TokenTransfer::payout_from_vault(payout, arbiter, arbiter_token_account, fee)?;
+
+    escrow_data.arbiter_fee_paid = escrow_data
+        .arbiter_fee_paid
+        .checked_add(fee)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    msg!("Arbiter fee {} paid to {}", fee, escrow_data.arbiter);
+
+    Ok(fee)
+}
+
+/// Pays `amount` from the vault to the seller, deducting the escrow's settlement fee
+/// (if any) for the fee collector. Returns the fee charged.
+///
+/// The fee destination is the last account of the instruction: the fee collector for
+/// SOL escrows or its token account for SPL escrows. It is only required when a fee is due.
+fn pay_seller<'a>(
+    escrow_data: &EscrowAccount,
+    payout: &VaultPayout<'a, '_>,
+    seller: &AccountInfo<'a>,
+    seller_token_account: Option<&AccountInfo<'a>>,
+    fee_destination: Option<&AccountInfo<'a>>,
+    amount: u64,
+) -> Result<u64, ProgramError> {
+    let fee = AmountHelper::basis_points_of(amount, escrow_data.settlement_fee_bps)?;
+    if fee > 0 {
+        let fee_destination = fee_destination.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        if TokenTransfer::is_native_mint(&escrow_data.mint) {
+            ValidationHelper::validate_fee_collector(fee_destination, &escrow_data.fee_collector)?;
+            TokenTransfer::payout_from_vault(payout, fee_destination, None, fee)?;
+        } else {
+            ValidationHelper::validate_token_account(
+                fee_destination,
+                &escrow_data.mint,
+                &escrow_data.fee_collector,
+                "fee collector token account",
+            )?;
+            TokenTransfer::payout_tokens_from_vault(payout, fee_destination, fee)?;
+        }
+        msg!("Settlement fee {} paid to {}", fee, escrow_data.fee_collector);
+    }
+
+    TokenTransfer::payout_from_vault(payout, seller, seller_token_account, amount - fee)?;
+    Ok(fee)
+}
+
+/// Pay the whole seller bond held in the vault to one party: back to the seller, or
+/// forfeited to the buyer. The caller validates `recipient`.
+fn release_collateral<'a>(
+    escrow: &Pubkey,
+    escrow_data: &EscrowAccount,
+    payout: &VaultPayout<'a, '_>,
+    recipient: &AccountInfo<'a>,
+    recipient_token_account: Option<&AccountInfo<'a>>,
+    to_seller: bool,
+) -> ProgramResult {
+    let bond = escrow_data.held_collateral()?;
+    if bond == 0 {
+        return Ok(());
+    }
+
+    TokenTransfer::payout_from_vault(payout, recipient, recipient_token_account, bond)?;
+
+    let (seller_amount, buyer_amount) = if to_seller { (bond, 0) } else { (0, bond) };
+    msg!("Seller collateral {} {}", bond, if to_seller { "returned to seller" } else { "forfeited to buyer" });
+    EscrowEvent::CollateralReleased { escrow: *escrow, seller_amount, buyer_amount }.emit();
+
+    Ok(())
+}
+
+/// Creates the singleton ProgramConfig PDA. Only the program upgrade authority may do this.
+///
+/// # Accounts
+/// * `[signer, writable]` authority - Program upgrade authority, becomes config admin and pays rent
+/// * `[writable]` config - ProgramConfig PDA (`[b"config"]`)
+/// * `[]` program_data - ProgramData account of this program
+/// * `[]` system_program - System program
+///
+/// # Instruction Data
+/// * byte 0: instruction type (18)
+/// * bytes 1-32: fee_collector pubkey (32 bytes)
+/// * bytes 33-40: flat fee in lamports (u64, little-endian)
+/// * bytes 41-42: percentage fee in basis points (u16, little-endian; SPL escrows always pay it at settlement)
+/// * byte 43: fee mode (0 = charged upfront at creation, 1 = deducted at settlement)
+/// * bytes 44-45: share of the upfront fee refunded on `CancelOffer`, in basis points (u16, little-endian)
+fn initialize_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    config_args: ConfigArgs,
+) -> ProgramResult {
+    let ConfigArgs {
+        fee_collector,
+        flat_fee,
+        fee_bps,
+        fee_on_settlement,
+        cancel_refund_bps,
+    } = config_args;
+
+    if fee_bps > BASIS_POINTS_DENOMINATOR || cancel_refund_bps > BASIS_POINTS_DENOMINATOR {
+        msg!("Fee basis points must not exceed {}", BASIS_POINTS_DENOMINATOR);
+        return Err(EscrowError::InvalidBasisPoints.into());
+    }
+
+    let accounts_iter = &mut accounts.iter();
+    let authority = next_account_info(accounts_iter)?;
+    let config_account = next_account_info(accounts_iter)?;
+    let program_data = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    // Validations
+    ValidationHelper::validate_signer(authority, "Authority")?;
+    ValidationHelper::validate_upgrade_authority(program_data, authority.key, program_id)?;
+
+    let (config_pda, config_bump) = pda::find_config_address(program_id);
+    if config_pda != *config_account.key {
+        msg!("Invalid config PDA");
+        return Err(EscrowError::InvalidConfigAccount.into());
+    }
+    if config_account.lamports() > 0 {
+        msg!("Config already initialized");
+        return Err(EscrowError::AccountAlreadySet.into());
+    }
+
+    let rent = Rent::get()?;
+    AccountHelper::create_pda_account(
+        authority,
+        config_account,
+        system_program,
+        program_id,
+        &[CONFIG_SEED, &[config_bump]],
+        ProgramConfig::LEN as u64,
+        rent.minimum_balance(ProgramConfig::LEN),
+    )?;
+
+    let config = ProgramConfig {
+        admin: *authority.key,
+        fee_collector,
+        flat_fee,
+        fee_bps,
+        bump: config_bump,
+        fee_on_settlement,
+        cancel_refund_bps,
+    };
+    config.save_to_account(config_account)?;
+
+    msg!("Config initialized. Admin: {}", config.admin);
+    msg!("Fee collector: {}, flat fee: {}, fee bps: {}", fee_collector, flat_fee, fee_bps);
+
+    Ok(())
+}
+
+/// Updates the ProgramConfig. Only the current admin may do this.
+///
+/// # Accounts
+/// * `[signer]` admin - Current config admin
+/// * `[writable]` config - ProgramConfig PDA
+///
+/// # Instruction Data
+/// * byte 0: instruction type (19)
+/// * bytes 1-32: new admin pubkey (32 bytes)
+/// * bytes 33-64: fee_collector pubkey (32 bytes)
+/// * bytes 65-72: flat fee in lamports (u64, little-endian)
+/// * bytes 73-74: percentage fee in basis points (u16, little-endian; SPL escrows always pay it at settlement)
+/// * byte 75: fee mode (0 = charged upfront at creation, 1 = deducted at settlement)
+/// * bytes 76-77: share of the upfront fee refunded on `CancelOffer`, in basis points (u16, little-endian)
+///
+/// Live escrows keep the settlement fee rate they were created with.
+fn update_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_admin: Pubkey,
+    config_args: ConfigArgs,
+) -> ProgramResult {
+    let ConfigArgs {
+        fee_collector,
+        flat_fee,
+        fee_bps,
+        fee_on_settlement,
+        cancel_refund_bps,
+    } = config_args;
+
+    if fee_bps > BASIS_POINTS_DENOMINATOR || cancel_refund_bps > BASIS_POINTS_DENOMINATOR {
+        msg!("Fee basis points must not exceed {}", BASIS_POINTS_DENOMINATOR);
+        return Err(EscrowError::InvalidBasisPoints.into());
+    }
+
+    let accounts_iter = &mut accounts.iter();
+    let admin = next_account_info(accounts_iter)?;
+    let config_account = next_account_info(accounts_iter)?;
+
+    // Validations
+    ValidationHelper::validate_signer(admin, "Admin")?;
+
+    let mut config = ValidationHelper::load_config(config_account, program_id)?;
+    ValidationHelper::validate_account_key(admin, &config.admin, "admin", EscrowError::NotConfigAdmin)?;
+
+    config.admin = new_admin;
+    config.fee_collector = fee_collector;
+    config.flat_fee = flat_fee;
+    config.fee_bps = fee_bps;
+    config.fee_on_settlement = fee_on_settlement;
+    config.cancel_refund_bps = cancel_refund_bps;
+    config.save_to_account(config_account)?;
+
+    msg!("Config updated. Admin: {}", new_admin);
+    msg!("Fee collector: {}, flat fee: {}, fee bps: {}", fee_collector, flat_fee, fee_bps);
+
+    Ok(())
+}
+
+/// Buyer and seller mutually cancel escrow.
+/// The buyer gets the vault back, the seller its bond.
+///
+/// # Accounts
+/// * `[signer, writable]` buyer - Buyer (receives SOL refund)
+/// * `[signer, writable]` seller - Seller (receives a SOL seller bond)
+/// * `[writable]` escrow_account - Escrow data account
+/// * `[writable]` vault - Vault PDA
+/// * `[]` mint - SPL token mint (optional, SPL only)
+/// * `[writable]` vault_token_account - Vault PDA's associated token account (optional, SPL only)
+/// * `[writable]` buyer_token_account - Buyer token account (optional, SPL only)
+/// * `[]` token_program - SPL Token or Token-2022 program (optional, SPL only)
+/// * `[writable]` seller_token_account - Seller token account (optional, SPL only, when a seller bond is held)
+fn mutual_cancel(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let buyer = next_account_info(accounts_iter)?;
+    let seller = next_account_info(accounts_iter)?;
+    let escrow_account = next_account_info(accounts_iter)?;
+    let vault = next_account_info(accounts_iter)?;
+    let mint_account = next_account_info(accounts_iter).ok();
+    let vault_token_account = next_account_info(accounts_iter).ok();
+    let buyer_token_account = next_account_info(accounts_iter).ok();
+    let token_program = next_account_info(accounts_iter).ok();
+    let seller_token_account = next_account_info(accounts_iter).ok();
+
+    // Both parties must sign
+    if !buyer.is_signer || !seller.is_signer {
+        msg!("Both buyer and seller must sign");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    
+    ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
+
+    let mut escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
+
+    ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
+    ValidationHelper::validate_account_key(buyer, &escrow_data.buyer, "buyer", EscrowError::NotBuyer)?;
+    ValidationHelper::validate_account_key(seller, &escrow_data.seller, "seller", EscrowError::NotSeller)?;
+
+    let state = escrow_data.get_state()?;
+    let signers = Actor::of(&escrow_data, buyer.key).and(Actor::of(&escrow_data, seller.key));
+    let new_state = next_state(&escrow_data, Action::MutualCancel, signers)?;
+
+    // If funded, return funds to buyer
+    let refund = if state.holds_funds() { escrow_data.remaining_amount()? } else { 0 };
+    let payout = VaultPayout {
+        mint: &escrow_data.mint,
+        mint_account,
+        vault,
+        vault_token_account,
+        token_program,
+        vault_seeds: &[VAULT_SEED, escrow_account.key.as_ref(), &[escrow_data.vault_bump]],
+    };
+    TokenTransfer::payout_from_vault(&payout, buyer, buyer_token_account, refund)?;
+    release_collateral(escrow_account.key, &escrow_data, &payout, seller, seller_token_account, true)?;
+
+    escrow_data.set_state(new_state);
+    escrow_data.save_to_account(escrow_account)?;
+    
+    msg!("Escrow mutually cancelled");
+    EscrowEvent::Refunded {
+        escrow: *escrow_account.key,
+        buyer: *buyer.key,
+        amount: refund,
+        arbiter_fee: 0,
+        milestone: None,
+    }
+    .emit();
+    
+    Ok(())
+}
+
+/// Refunds the buyer after the delivery deadline passed without seller confirmation;
+/// a seller bond is forfeited to the buyer as well. Milestone escrows are refunded only
+/// while no milestone awaits release: a seller-confirmed milestone must be released by
+/// the buyer, or disputed and ruled on by the arbiter, first.
+/// Unfunded offers past the funding deadline are cancelled, only returning a deposited
+/// seller bond to the seller.
+/// Permissionless: anyone may crank this once the deadline is reached.
+///
+/// # Accounts
+/// * `[writable]` escrow_account - Escrow data account
+/// * `[writable]` vault - Vault PDA
+/// * `[writable]` buyer - Buyer account (receives SOL refund)
+/// * `[writable]` seller - Seller account (receives a returned SOL seller bond)
+/// * `[]` milestone_account - Milestone PDA (read for milestone escrows, ignored otherwise)
+/// * `[]` mint - SPL token mint (optional, SPL only)
+/// * `[writable]` vault_token_account - Vault PDA's associated token account (optional, SPL only)
+/// * `[writable]` buyer_token_account - Buyer token account (optional, SPL only)
+/// * `[]` token_program - SPL Token or Token-2022 program (optional, SPL only)
+/// * `[writable]` seller_token_account - Seller token account (optional, SPL only, only to return a seller bond)
+fn claim_expired_refund(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let escrow_account = next_account_info(accounts_iter)?;
+    let vault = next_account_info(accounts_iter)?;
+    let buyer = next_account_info(accounts_iter)?;
+    let seller = next_account_info(accounts_iter)?;
+    let milestone_account = next_account_info(accounts_iter)?;
+    let mint_account = next_account_info(accounts_iter).ok();
+    let vault_token_account = next_account_info(accounts_iter).ok();
+    let buyer_token_account = next_account_info(accounts_iter).ok();
+    let token_program = next_account_info(accounts_iter).ok();
+    let seller_token_account = next_account_info(accounts_iter).ok();
+
+    // Validations
+    ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
+
+    let mut escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
+
+    ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
+
+    let state = escrow_data.get_state()?;
+    let new_state = next_state(&escrow_data, Action::ClaimExpiredRefund, Actor::ANYONE)?;
+    let payout = VaultPayout {
+        mint: &escrow_data.mint,
+        mint_account,
+        vault,
+        vault_token_account,
+        token_program,
+        vault_seeds: &[VAULT_SEED, escrow_account.key.as_ref(), &[escrow_data.vault_bump]],
+    };
+    let mut refund = 0;
+    if state.holds_funds() {
+        ValidationHelper::validate_account_key(buyer, &escrow_data.buyer, "buyer", EscrowError::NotBuyer)?;
+        // Delivered milestones are the seller's unless the buyer disputes them
+        if escrow_data.is_milestone_escrow()
+            && load_milestones(program_id, milestone_account, escrow_account.key)?.any_seller_confirmed()?
+        {
+            msg!("A seller-confirmed milestone awaits release");
+            return Err(EscrowError::MilestonesAwaitingRelease.into());
+        }
+        refund = escrow_data.remaining_amount()?;
+
+        // Return funds to buyer, the seller failed to deliver and loses its bond
+        TokenTransfer::payout_from_vault(&payout, buyer, buyer_token_account, refund)?;
+        release_collateral(escrow_account.key, &escrow_data, &payout, buyer, buyer_token_account, false)?;
+
+        msg!("Delivery deadline passed. Funds returned to buyer");
+    } else {
+        // The buyer never funded, so the seller gets its bond back
+        if escrow_data.held_collateral()? > 0 {
+            ValidationHelper::validate_account_key(seller, &escrow_data.seller, "seller", EscrowError::NotSeller)?;
+            release_collateral(escrow_account.key, &escrow_data, &payout, seller, seller_token_account, true)?;
+        }
+        msg!("Funding deadline passed. Offer expired");
+    }
+
+    escrow_data.set_state(new_state);
+    escrow_data.save_to_account(escrow_account)?;
+
+    EscrowEvent::Refunded {
+        escrow: *escrow_account.key,
+        buyer: escrow_data.buyer,
+        amount: refund,
+        arbiter_fee: 0,
+        milestone: None,
+    }
+    .emit();
+
+    Ok(())
+}
+
+/// Releases funds to the seller once the buyer's dispute window has elapsed
+/// after seller confirmation without the buyer confirming.
+/// Permissionless: anyone may crank this once the window is over.
+///
+/// # Accounts
+/// * `[writable]` escrow_account - Escrow data account
+/// * `[writable]` vault - Vault PDA
+/// * `[writable]` seller - Seller account (receives SOL)
+/// * `[]` mint - SPL token mint (optional, SPL only)
+/// * `[writable]` vault_token_account - Vault PDA's associated token account (optional, SPL only)
+/// * `[writable]` seller_token_account - Seller token account (optional, SPL only)
+/// * `[]` token_program - SPL Token or Token-2022 program (optional, SPL only)
+/// * `[writable]` fee_destination - Fee collector (SOL) or its token account (SPL), last account, only when a settlement fee is due
+fn claim_auto_release(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let escrow_account = next_account_info(accounts_iter)?;
+    let vault = next_account_info(accounts_iter)?;
+    let seller = next_account_info(accounts_iter)?;
+    let mint_account = next_account_info(accounts_iter).ok();
+    let vault_token_account = next_account_info(accounts_iter).ok();
+    let seller_token_account = next_account_info(accounts_iter).ok();
+    let token_program = next_account_info(accounts_iter).ok();
+
+    // Validations
+    ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
+
+    let mut escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
+
+    let new_state = next_state(&escrow_data, Action::ClaimAutoRelease, Actor::ANYONE)?;
+
+    ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
+    ValidationHelper::validate_account_key(seller, &escrow_data.seller, "seller", EscrowError::NotSeller)?;
+
+    // Transfer funds to seller, minus the settlement fee
+    let payout = VaultPayout {
+        mint: &escrow_data.mint,
+        mint_account,
+        vault,
+        vault_token_account,
+        token_program,
+        vault_seeds: &[VAULT_SEED, escrow_account.key.as_ref(), &[escrow_data.vault_bump]],
+    };
+    let amount = escrow_data.remaining_amount()?;
+    let fee = pay_seller(&escrow_data, &payout, seller, seller_token_account, accounts.last(), amount)?;
+    release_collateral(escrow_account.key, &escrow_data, &payout, seller, seller_token_account, true)?;
+
+    escrow_data.set_state(new_state);
+    escrow_data.save_to_account(escrow_account)?;
+
+    msg!("Dispute window elapsed. Funds auto-released to seller");
+    EscrowEvent::Released {
+        escrow: *escrow_account.key,
+        seller: *seller.key,
+        amount: amount - fee,
+        fee,
+        arbiter_fee: 0,
+        milestone: None,
+    }
+    .emit();
+
+    Ok(())
+}
+
+/// Buyer or seller flags the escrow for arbitration
+///
+/// # Accounts
+/// * `[signer]` disputant - Buyer or seller raising the dispute
+/// * `[writable]` escrow_account - Escrow data account
+fn raise_dispute(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let disputant = next_account_info(accounts_iter)?;
+    let escrow_account = next_account_info(accounts_iter)?;
+
+    // Validations
+    ValidationHelper::validate_signer(disputant, "Disputant")?;
+    ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
+
+    let mut escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
+
+    let new_state = next_state(&escrow_data, Action::RaiseDispute, Actor::of(&escrow_data, disputant.key))?;
+
+    escrow_data.dispute_raised_by = *disputant.key;
+    escrow_data.disputed_at = Clock::get()?.unix_timestamp;
+    escrow_data.set_state(new_state);
+    escrow_data.save_to_account(escrow_account)?;
+
+    msg!("Dispute raised by {}", disputant.key);
+    EscrowEvent::Disputed { escrow: *escrow_account.key, raised_by: *disputant.key }.emit();
+
+    Ok(())
+}
+
+/// Close escrow account, returning its rent (and the vault and milestone rent) to the
+/// rent payer recorded at creation. Permissionless: anyone may crank this once the escrow
+/// is completed or cancelled.
+/// For SPL escrows the vault token account is closed as well and its rent goes to the rent
+/// payer too. Closing is refused while the vault still holds tokens.
+///
+/// # Accounts
+/// * `[writable]` rent_payer - Account that paid the escrow rent in `create_offer`
+/// * `[writable]` escrow_account - Escrow data account
+/// * `[writable]` vault - Vault PDA
+/// * `[writable]` milestone_account - Milestone PDA (required for milestone escrows, ignored otherwise)
+/// * `[writable]` vault_token_account - Vault PDA's associated token account (SPL only)
+/// * `[]` token_program - SPL Token or Token-2022 program (SPL only)
+fn close_escrow(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let rent_payer = next_account_info(accounts_iter)?;
+    let escrow_account = next_account_info(accounts_iter)?;
+    let vault = next_account_info(accounts_iter)?;
+    let milestone_account = next_account_info(accounts_iter).ok();
+    let vault_token_account = next_account_info(accounts_iter).ok();
+    let token_program = next_account_info(accounts_iter).ok();
+
+    ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
+
+    let escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
+
+    next_state(&escrow_data, Action::CloseEscrow, Actor::ANYONE)?;
+
+    ValidationHelper::validate_account_key(rent_payer, &escrow_data.rent_payer, "rent payer", EscrowError::NotRentPayer)?;
+    ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
+    let milestone_account = if escrow_data.is_milestone_escrow() {
+        let milestone_account = milestone_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        load_milestones(program_id, milestone_account, escrow_account.key)?;
+        Some(milestone_account)
+    } else {
+        None
+    };
+
+    // Close the vault token account first (SPL only); it must be empty
+    if !TokenTransfer::is_native_mint(&escrow_data.mint) {
+        let vault_token_account = vault_token_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let token_program = token_program.ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+        // Only the vault's own ATA may be skipped for having no lamports
+        TokenTransfer::validate_token_program(token_program)?;
+        let expected = AccountHelper::associated_token_address(vault.key, &escrow_data.mint, token_program.key);
+        ValidationHelper::validate_account_key(
+            vault_token_account,
+            &expected,
+            "vault token account",
+            EscrowError::InvalidVaultTokenAccount,
+        )?;
+
+        // Offers cancelled before funding never got a vault token account
+        if vault_token_account.lamports() > 0 {
+            ValidationHelper::validate_vault_token_account(
+                vault_token_account,
+                vault.key,
+                &escrow_data.mint,
+                token_program.key,
+            )?;
+
+            let vault_token_balance = TokenTransfer::token_account_amount(vault_token_account)?;
+            if vault_token_balance > 0 {
+                msg!("Vault token account still holds {} tokens", vault_token_balance);
+                return Err(EscrowError::VaultNotEmpty.into());
+            }
+
+            TokenTransfer::close_token_account(
+                vault_token_account,
+                rent_payer,
+                vault,
+                token_program,
+                Some(&[VAULT_SEED, escrow_account.key.as_ref(), &[escrow_data.vault_bump]]),
+            )?;
+            msg!("Vault token account closed, rent returned to {}", rent_payer.key);
+        } else if escrow_data.funded_amount > 0 || escrow_data.funded_bond > 0 {
+            // Funding created it, so this is the ATA under the other token program
+            msg!("Vault token account {} of a funded escrow does not exist", vault_token_account.key);
+            return Err(EscrowError::InvalidVaultTokenAccount.into());
+        }
+    }
+
+    // Close escrow, vault and milestone accounts - return all lamports and zero out data
+    let mut returned = AccountHelper::close_account(escrow_account, rent_payer)?;
+    returned += AccountHelper::close_account(vault, rent_payer)?;
+    if let Some(milestone_account) = milestone_account {
+        returned += AccountHelper::close_account(milestone_account, rent_payer)?;
+    }
+
+    msg!("Escrow closed. {} lamports returned to {}", returned, rent_payer.key);
+    EscrowEvent::Closed { escrow: *escrow_account.key, rent_payer: *rent_payer.key, lamports: returned }.emit();
+    
+    Ok(())
+}
+
+/// Lets the initiator withdraw an offer nobody has joined yet. Closes the escrow, vault
+/// and milestone accounts in one step and refunds their rent together with the held
+/// (refundable) share of the service fee to the recorded rent payer.
+///
+/// # Accounts
+/// * `[signer, writable]` initiator - Offer creator
+/// * `[writable]` escrow_account - Escrow data account
+/// * `[writable]` vault - Vault PDA
+/// * `[writable]` milestone_account - Milestone PDA (required for milestone escrows)
+/// * `[writable]` rent_payer - Account that paid the fee and rent in `create_offer` (optional, defaults to the initiator)
+///
+/// # Instruction Data
+/// * byte 0: instruction type (20)
+fn cancel_offer(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let initiator = next_account_info(accounts_iter)?;
+    let escrow_account = next_account_info(accounts_iter)?;
+    let vault = next_account_info(accounts_iter)?;
+    let milestone_account = next_account_info(accounts_iter).ok();
+    let rent_payer = next_account_info(accounts_iter).unwrap_or(initiator);
+
+    // Validations
+    ValidationHelper::validate_signer(initiator, "Initiator")?;
+    ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
+
+    let escrow_data = EscrowAccount::from_account_data(&escrow_account.try_borrow_data()?)?;
+
+    next_state(&escrow_data, Action::CancelOffer, Actor::of(&escrow_data, initiator.key))?;
+    ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
+    ValidationHelper::validate_account_key(rent_payer, &escrow_data.rent_payer, "rent payer", EscrowError::NotRentPayer)?;
+
+    let mut returned = 0;
+    if escrow_data.is_milestone_escrow() {
+        let milestone_account = milestone_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+        load_milestones(program_id, milestone_account, escrow_account.key)?;
+        returned += AccountHelper::close_account(milestone_account, rent_payer)?;
+    }
+
+    // The vault holds its rent plus any refundable service fee
+    returned += AccountHelper::close_account(vault, rent_payer)?;
+    returned += AccountHelper::close_account(escrow_account, rent_payer)?;
+
+    msg!("Offer cancelled by initiator");
+    msg!("Refunded {} lamports to {} (service fee share: {})", returned, rent_payer.key, escrow_data.held_fee);
+    EscrowEvent::Closed { escrow: *escrow_account.key, rent_payer: *rent_payer.key, lamports: returned }.emit();
+
+    Ok(())
+}
+
+/// Upgrades an escrow stored in the original unversioned layout to the current one.
+/// The account is reallocated in place and `payer` tops up the extra rent.
+/// Permissionless: the migrated data is fully determined by the old account.
+///
+/// # Accounts
+/// * `[signer, writable]` payer - Pays the rent for the larger account
+/// * `[writable]` escrow_account - Escrow data account
+/// * `[]` system_program - System program
+///
+/// # Instruction Data
+/// * byte 0: instruction type (21)
+fn migrate_escrow(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let escrow_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    // Validations
+    ValidationHelper::validate_signer(payer, "Payer")?;
+    ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
+    ValidationHelper::validate_system_program(system_program)?;
+
+    let (escrow_data, version) = EscrowAccount::from_any_version(&escrow_account.try_borrow_data()?)?;
+    if version == ESCROW_VERSION {
+        msg!("Escrow already uses layout version {}", ESCROW_VERSION);
+        return Ok(());
+    }
+
+    // Top up rent for the new size
+    let required_lamports = Rent::get()?.minimum_balance(EscrowAccount::LEN);
+    let top_up = required_lamports.saturating_sub(escrow_account.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, escrow_account.key, top_up),
+            &[payer.clone(), escrow_account.clone(), system_program.clone()],
+        )?;
+    }
+
+    escrow_account.realloc(EscrowAccount::LEN, false)?;
+    escrow_data.save_to_account(escrow_account)?;
+
+    msg!("Escrow migrated from layout version {} to {}", version, ESCROW_VERSION);
+    msg!("Rent top-up: {} lamports", top_up);
+
+    Ok(())
+}
+
+/// Get escrow information.
+/// Sets the Borsh-encoded `EscrowInfo` as return data, for simulations and calling programs.
+///
+/// # Accounts
+/// * `[]` escrow_account - Escrow data account (any layout version)
+/// * `[]` vault - Vault PDA (optional, to report the vault balance)
+/// * `[]` vault_token_account - Vault PDA's associated token account (optional, SPL only)
+fn get_escrow_info(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let escrow_account = next_account_info(accounts_iter)?;
+    let vault = next_account_info(accounts_iter).ok();
+    let vault_token_account = next_account_info(accounts_iter).ok();
+
+    ValidationHelper::validate_program_account(escrow_account, program_id, "escrow_account")?;
+
+    let (escrow_data, version) = EscrowAccount::from_any_version(&escrow_account.try_borrow_data()?)?;
+
+    let vault_balance = match vault {
+        Some(vault) => {
+            ValidationHelper::validate_vault_pda(vault, escrow_account.key, program_id, escrow_data.vault_bump)?;
+            if TokenTransfer::is_native_mint(&escrow_data.mint) {
+                Some(vault.lamports())
+            } else {
+                let vault_token_account = vault_token_account.ok_or(ProgramError::NotEnoughAccountKeys)?;
+                // Created on funding
+                if vault_token_account.lamports() == 0 {
+                    Some(0)
+                } else {
+                    if !TokenTransfer::is_token_program(vault_token_account.owner) {
+                        msg!("Vault token account is not owned by a token program: {}", vault_token_account.owner);
+                        return Err(ProgramError::IncorrectProgramId);
+                    }
+                    ValidationHelper::validate_vault_token_account(
+                        vault_token_account,
+                        vault.key,
+                        &escrow_data.mint,
+                        vault_token_account.owner,
+                    )?;
+                    Some(TokenTransfer::token_account_amount(vault_token_account)?)
+                }
+            }
+        }
+        None => None,
+    };
+
+    let info = EscrowInfo::new(escrow_data, version, Clock::get()?.unix_timestamp, vault_balance)?;
+    msg!("State: {:?}, vault balance: {:?}", info.escrow.get_state()?, info.vault_balance);
+    let return_data = borsh::to_vec(&info).map_err(|err| ProgramError::BorshIoError(err.to_string()))?;
+    set_return_data(&return_data);
+
     Ok(())
 }
\ No newline at end of file
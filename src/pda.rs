@@ -0,0 +1,121 @@
+//! PDA seed scheme of the program, shared by the on-chain handlers and off-chain clients.
+//!
+//! * escrow: `[b"escrow", random_seed]`
+//! * vault: `[b"vault", escrow_key]`
+//! * vault token account: associated token account of the vault PDA
+//! * milestones: `[b"milestones", escrow_key]`
+//! * config: `[b"config"]`
+
+use solana_program::pubkey::{Pubkey, PubkeyError};
+
+use crate::utils::AccountHelper;
+
+pub const ESCROW_SEED: &[u8] = b"escrow";
+pub const VAULT_SEED: &[u8] = b"vault";
+pub const MILESTONE_SEED: &[u8] = b"milestones";
+pub const CONFIG_SEED: &[u8] = b"config";
+
+/// Escrow account of an offer created with `random_seed`
+pub fn find_escrow_address(program_id: &Pubkey, random_seed: &[u8; 32]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ESCROW_SEED, random_seed], program_id)
+}
+
+/// Vault PDA holding the escrowed SOL and owning the vault token account
+pub fn find_vault_address(program_id: &Pubkey, escrow: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[VAULT_SEED, escrow.as_ref()], program_id)
+}
+
+/// Vault PDA from the bump recorded in the escrow
+pub fn create_vault_address(program_id: &Pubkey, escrow: &Pubkey, bump: u8) -> Result<Pubkey, PubkeyError> {
+    Pubkey::create_program_address(&[VAULT_SEED, escrow.as_ref(), &[bump]], program_id)
+}
+
+/// Vault token account of an SPL escrow: the vault PDA's associated token account
+pub fn find_vault_token_address(
+    program_id: &Pubkey,
+    escrow: &Pubkey,
+    mint: &Pubkey,
+    token_program_id: &Pubkey,
+) -> Pubkey {
+    let (vault, _) = find_vault_address(program_id, escrow);
+    AccountHelper::associated_token_address(&vault, mint, token_program_id)
+}
+
+/// Milestone account of a milestone escrow
+pub fn find_milestone_address(program_id: &Pubkey, escrow: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MILESTONE_SEED, escrow.as_ref()], program_id)
+}
+
+/// Milestone account from the bump recorded in it
+pub fn create_milestone_address(program_id: &Pubkey, escrow: &Pubkey, bump: u8) -> Result<Pubkey, PubkeyError> {
+    Pubkey::create_program_address(&[MILESTONE_SEED, escrow.as_ref(), &[bump]], program_id)
+}
+
+/// Singleton ProgramConfig account
+pub fn find_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONFIG_SEED], program_id)
+}
+
+/// ProgramConfig account from the bump recorded in it
+pub fn create_config_address(program_id: &Pubkey, bump: u8) -> Result<Pubkey, PubkeyError> {
+    Pubkey::create_program_address(&[CONFIG_SEED, &[bump]], program_id)
+}
+
+/// ProgramData account of this program under the BPF upgradeable loader
+pub fn find_program_data_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[program_id.as_ref()], &solana_program::bpf_loader_upgradeable::id()).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{ASSOCIATED_TOKEN_PROGRAM_ID, SPL_TOKEN_PROGRAM_ID};
+    use solana_program::pubkey;
+
+    const PROGRAM_ID: Pubkey = pubkey!("Esc11111111111111111111111111111111111111111");
+
+    #[test]
+    fn test_seed_scheme() {
+        let seed = [9u8; 32];
+        let (escrow, escrow_bump) = find_escrow_address(&PROGRAM_ID, &seed);
+        assert_eq!(
+            Pubkey::create_program_address(&[b"escrow", &seed, &[escrow_bump]], &PROGRAM_ID).unwrap(),
+            escrow
+        );
+
+        let (vault, vault_bump) = find_vault_address(&PROGRAM_ID, &escrow);
+        assert_eq!(
+            Pubkey::create_program_address(&[b"vault", escrow.as_ref(), &[vault_bump]], &PROGRAM_ID).unwrap(),
+            vault
+        );
+        assert_eq!(create_vault_address(&PROGRAM_ID, &escrow, vault_bump).unwrap(), vault);
+
+        let (milestones, milestone_bump) = find_milestone_address(&PROGRAM_ID, &escrow);
+        assert_eq!(
+            Pubkey::create_program_address(&[b"milestones", escrow.as_ref(), &[milestone_bump]], &PROGRAM_ID)
+                .unwrap(),
+            milestones
+        );
+        assert_eq!(create_milestone_address(&PROGRAM_ID, &escrow, milestone_bump).unwrap(), milestones);
+
+        let (config, config_bump) = find_config_address(&PROGRAM_ID);
+        assert_eq!(
+            Pubkey::create_program_address(&[b"config", &[config_bump]], &PROGRAM_ID).unwrap(),
+            config
+        );
+        assert_eq!(create_config_address(&PROGRAM_ID, config_bump).unwrap(), config);
+    }
+
+    #[test]
+    fn test_vault_token_address_is_vault_ata() {
+        let escrow = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let (vault, _) = find_vault_address(&PROGRAM_ID, &escrow);
+
+        let (expected, _) = Pubkey::find_program_address(
+            &[vault.as_ref(), SPL_TOKEN_PROGRAM_ID.as_ref(), mint.as_ref()],
+            &ASSOCIATED_TOKEN_PROGRAM_ID,
+        );
+        assert_eq!(find_vault_token_address(&PROGRAM_ID, &escrow, &mint, &SPL_TOKEN_PROGRAM_ID), expected);
+    }
+}
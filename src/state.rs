@@ -1,3 +1,4 @@
+use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
     account_info::AccountInfo,
     entrypoint::ProgramResult,
@@ -6,6 +7,26 @@ use solana_program::{
     pubkey::Pubkey,
 };
 
+use crate::instructions::EscrowError;
+use crate::utils::AmountHelper;
+
+/// Length of the account type discriminator at the start of every program account
+pub const DISCRIMINATOR_LEN: usize = 8;
+
+/// Account type discriminators: first 8 bytes of `sha256("account:<TypeName>")`
+pub const ESCROW_DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [36, 69, 48, 18, 128, 225, 125, 135];
+pub const MILESTONE_DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [21, 222, 32, 140, 43, 166, 109, 19];
+pub const CONFIG_DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [196, 210, 90, 231, 144, 149, 140, 63];
+
+/// Check that `data` starts with the discriminator of the expected account type
+pub fn check_discriminator(data: &[u8], expected: &[u8; DISCRIMINATOR_LEN], account_type: &str) -> ProgramResult {
+    if data.len() < DISCRIMINATOR_LEN || data[..DISCRIMINATOR_LEN] != expected[..] {
+        msg!("Account is not a {} account", account_type);
+        return Err(EscrowError::InvalidAccountDiscriminator.into());
+    }
+    Ok(())
+}
+
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum EscrowState {
@@ -17,6 +38,7 @@ pub enum EscrowState {
     BuyerConfirmed = 5,
     Completed = 6,
     Cancelled = 7,
+    Disputed = 8,
 }
 
 impl EscrowState {
@@ -30,16 +52,44 @@ impl EscrowState {
             5 => Ok(EscrowState::BuyerConfirmed),
             6 => Ok(EscrowState::Completed),
             7 => Ok(EscrowState::Cancelled),
+            8 => Ok(EscrowState::Disputed),
             _ => {
                 msg!("Invalid escrow state: {}", value);
-                Err(ProgramError::InvalidAccountData)
+                Err(EscrowError::InvalidAccountLayout.into())
             }
         }
     }
+
+    /// Completed or cancelled; no action other than closing is accepted
+    pub fn is_terminal(self) -> bool {
+        matches!(self, EscrowState::Completed | EscrowState::Cancelled)
+    }
+
+    /// Funded and not yet settled: the vault holds the escrowed amount
+    pub fn holds_funds(self) -> bool {
+        matches!(self, EscrowState::Funded | EscrowState::SellerConfirmed | EscrowState::Disputed)
+    }
 }
 
+/// Party has agreed to let the arbiter rule without a formal dispute
+pub const ARBITER_OPT_IN_BUYER: u8 = 1 << 0;
+pub const ARBITER_OPT_IN_SELLER: u8 = 1 << 1;
+
+/// Arbiter fee is a fixed amount in the escrow's mint
+pub const ARBITER_FEE_FIXED: u8 = 0;
+/// Arbiter fee is a share of the ruled amount in basis points
+pub const ARBITER_FEE_BPS: u8 = 1;
+
+/// Layout version stored right after the discriminator of every escrow account
+pub const ESCROW_VERSION: u8 = 1;
+
+/// Size of the original unversioned (version 0) layout, which ends after `fee_collector`.
+/// It is a prefix of the current body.
+pub const LEGACY_ESCROW_LEN: usize = 170;
+
+/// Borsh encodes the fields in the order and widths of the account body
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
 pub struct EscrowAccount {
     pub buyer: Pubkey,
     pub seller: Pubkey,
@@ -49,10 +99,36 @@ pub struct EscrowAccount {
     pub vault_bump: u8,
     pub mint: Pubkey,
     pub fee_collector: Pubkey,
+    pub funding_deadline: i64,
+    pub delivery_deadline: i64,
+    pub dispute_window: i64,
+    pub seller_confirmed_at: i64,
+    pub dispute_raised_by: Pubkey,
+    pub disputed_at: i64,
+    pub arbiter_opt_in: u8,
+    pub ruling_buyer_amount: u64,
+    pub ruling_seller_amount: u64,
+    pub milestone_count: u8,
+    pub released_amount: u64,
+    pub settlement_fee_bps: u16,
+    pub arbiter_fee_mode: u8,
+    pub arbiter_fee: u64,
+    pub arbiter_fee_paid: u64,
+    pub funded_amount: u64,
+    pub rent_payer: Pubkey,
+    pub held_fee: u64,
+    /// Collateral the seller deposits before the buyer may fund, 0 = none
+    pub seller_bond: u64,
+    /// Collateral that actually arrived in the vault
+    pub funded_bond: u64,
 }
 
 impl EscrowAccount {
-    pub const LEN: usize = 32 + 32 + 32 + 8 + 1 + 1 + 32 + 32; // +32 для fee_collector
+    /// Size of the escrow fields, without the header; the widths follow the field order above
+    pub const BODY_LEN: usize = 32 + 32 + 32 + 8 + 1 + 1 + 32 + 32 + 8 + 8 + 8 + 8 + 32 + 8 + 1 + 8 + 8 + 1 + 8 + 2 + 1 + 8 + 8 + 8 + 32 + 8 + 8 + 8;
+    /// Discriminator and version byte in front of the escrow fields
+    pub const HEADER_LEN: usize = DISCRIMINATOR_LEN + 1;
+    pub const LEN: usize = Self::HEADER_LEN + Self::BODY_LEN;
 
     pub fn new(
         buyer: &Pubkey,
@@ -71,15 +147,77 @@ impl EscrowAccount {
             vault_bump,
             mint: *mint,
             fee_collector: *fee_collector,
+            funding_deadline: 0,
+            delivery_deadline: 0,
+            dispute_window: 0,
+            seller_confirmed_at: 0,
+            dispute_raised_by: Pubkey::default(),
+            disputed_at: 0,
+            arbiter_opt_in: 0,
+            ruling_buyer_amount: 0,
+            ruling_seller_amount: 0,
+            milestone_count: 0,
+            released_amount: 0,
+            settlement_fee_bps: 0,
+            arbiter_fee_mode: ARBITER_FEE_FIXED,
+            arbiter_fee: 0,
+            arbiter_fee_paid: 0,
+            funded_amount: 0,
+            rent_payer: *buyer,
+            held_fee: 0,
+            seller_bond: 0,
+            funded_bond: 0,
         }
     }
     
     pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
-        if data.len() != Self::LEN {
-            msg!("Invalid account size: expected {}, got {}", Self::LEN, data.len());
-            return Err(ProgramError::InvalidAccountData);
+        if Self::legacy_version(data).is_some() {
+            msg!("Escrow account uses a legacy layout, run MigrateEscrow first");
+            return Err(EscrowError::AccountNeedsMigration.into());
         }
-        
+        check_discriminator(data, &ESCROW_DISCRIMINATOR, "escrow")?;
+        if data.len() != Self::LEN || data[DISCRIMINATOR_LEN] != ESCROW_VERSION {
+            msg!("Invalid escrow account: expected {} bytes, version {}", Self::LEN, ESCROW_VERSION);
+            return Err(EscrowError::InvalidAccountLayout.into());
+        }
+
+        Self::unpack_body(&data[Self::HEADER_LEN..])
+    }
+
+    /// Layout version of an escrow stored in a layout older than the current one.
+    /// No other account type of this program is `LEGACY_ESCROW_LEN` bytes long.
+    fn legacy_version(data: &[u8]) -> Option<u8> {
+        (data.len() == LEGACY_ESCROW_LEN).then_some(0)
+    }
+
+    /// Read an escrow stored in the current or the unversioned layout.
+    /// Returns the escrow together with the layout version it was stored in.
+    pub fn from_any_version(data: &[u8]) -> Result<(Self, u8), ProgramError> {
+        match Self::legacy_version(data) {
+            None => Ok((Self::from_account_data(data)?, ESCROW_VERSION)),
+            Some(_) => Self::from_unversioned(data).map(|escrow| (escrow, 0)),
+        }
+    }
+
+    /// Fields the unversioned layout lacked read as zero, except the two derived below
+    fn from_unversioned(data: &[u8]) -> Result<Self, ProgramError> {
+        let mut body = [0u8; Self::BODY_LEN];
+        body[..data.len()].copy_from_slice(data);
+        let mut escrow = Self::unpack_body(&body)?;
+
+        // The vault always received the full amount
+        let funded = matches!(
+            escrow.get_state()?,
+            EscrowState::Funded | EscrowState::SellerConfirmed | EscrowState::BuyerConfirmed | EscrowState::Disputed
+        );
+        escrow.funded_amount = if funded { escrow.amount } else { 0 };
+        // The rent payer was not recorded; attribute it to the buyer, or the seller if no buyer joined yet
+        escrow.rent_payer = if escrow.buyer != Pubkey::default() { escrow.buyer } else { escrow.seller };
+
+        Ok(escrow)
+    }
+
+    fn unpack_body(data: &[u8]) -> Result<Self, ProgramError> {
         let buyer = Pubkey::new_from_array(data[0..32].try_into().unwrap());
         let seller = Pubkey::new_from_array(data[32..64].try_into().unwrap());
         let arbiter = Pubkey::new_from_array(data[64..96].try_into().unwrap());
@@ -88,6 +226,26 @@ impl EscrowAccount {
         let vault_bump = data[105];
         let mint = Pubkey::new_from_array(data[106..138].try_into().unwrap());
         let fee_collector = Pubkey::new_from_array(data[138..170].try_into().unwrap());
+        let funding_deadline = i64::from_le_bytes(data[170..178].try_into().unwrap());
+        let delivery_deadline = i64::from_le_bytes(data[178..186].try_into().unwrap());
+        let dispute_window = i64::from_le_bytes(data[186..194].try_into().unwrap());
+        let seller_confirmed_at = i64::from_le_bytes(data[194..202].try_into().unwrap());
+        let dispute_raised_by = Pubkey::new_from_array(data[202..234].try_into().unwrap());
+        let disputed_at = i64::from_le_bytes(data[234..242].try_into().unwrap());
+        let arbiter_opt_in = data[242];
+        let ruling_buyer_amount = u64::from_le_bytes(data[243..251].try_into().unwrap());
+        let ruling_seller_amount = u64::from_le_bytes(data[251..259].try_into().unwrap());
+        let milestone_count = data[259];
+        let released_amount = u64::from_le_bytes(data[260..268].try_into().unwrap());
+        let settlement_fee_bps = u16::from_le_bytes(data[268..270].try_into().unwrap());
+        let arbiter_fee_mode = data[270];
+        let arbiter_fee = u64::from_le_bytes(data[271..279].try_into().unwrap());
+        let arbiter_fee_paid = u64::from_le_bytes(data[279..287].try_into().unwrap());
+        let funded_amount = u64::from_le_bytes(data[287..295].try_into().unwrap());
+        let rent_payer = Pubkey::new_from_array(data[295..327].try_into().unwrap());
+        let held_fee = u64::from_le_bytes(data[327..335].try_into().unwrap());
+        let seller_bond = u64::from_le_bytes(data[335..343].try_into().unwrap());
+        let funded_bond = u64::from_le_bytes(data[343..351].try_into().unwrap());
         
         Ok(Self {
             buyer,
@@ -98,23 +256,67 @@ impl EscrowAccount {
             vault_bump,
             mint,
             fee_collector,
+            funding_deadline,
+            delivery_deadline,
+            dispute_window,
+            seller_confirmed_at,
+            dispute_raised_by,
+            disputed_at,
+            arbiter_opt_in,
+            ruling_buyer_amount,
+            ruling_seller_amount,
+            milestone_count,
+            released_amount,
+            settlement_fee_bps,
+            arbiter_fee_mode,
+            arbiter_fee,
+            arbiter_fee_paid,
+            funded_amount,
+            rent_payer,
+            held_fee,
+            seller_bond,
+            funded_bond,
         })
     }
     
     pub fn save_to_account(&self, account: &AccountInfo) -> ProgramResult {
         let mut data = account.try_borrow_mut_data()?;
         if data.len() < Self::LEN {
-            return Err(ProgramError::InvalidAccountData);
+            return Err(EscrowError::InvalidAccountLayout.into());
         }
-        
-        data[0..32].copy_from_slice(self.buyer.as_ref());
-        data[32..64].copy_from_slice(self.seller.as_ref());
-        data[64..96].copy_from_slice(self.arbiter.as_ref());
-        data[96..104].copy_from_slice(&self.amount.to_le_bytes());
-        data[104] = self.state;
-        data[105] = self.vault_bump;
-        data[106..138].copy_from_slice(self.mint.as_ref());
-        data[138..170].copy_from_slice(self.fee_collector.as_ref());
+
+        data[..DISCRIMINATOR_LEN].copy_from_slice(&ESCROW_DISCRIMINATOR);
+        data[DISCRIMINATOR_LEN] = ESCROW_VERSION;
+        let body = &mut data[Self::HEADER_LEN..];
+
+        body[0..32].copy_from_slice(self.buyer.as_ref());
+        body[32..64].copy_from_slice(self.seller.as_ref());
+        body[64..96].copy_from_slice(self.arbiter.as_ref());
+        body[96..104].copy_from_slice(&self.amount.to_le_bytes());
+        body[104] = self.state;
+        body[105] = self.vault_bump;
+        body[106..138].copy_from_slice(self.mint.as_ref());
+        body[138..170].copy_from_slice(self.fee_collector.as_ref());
+        body[170..178].copy_from_slice(&self.funding_deadline.to_le_bytes());
+        body[178..186].copy_from_slice(&self.delivery_deadline.to_le_bytes());
+        body[186..194].copy_from_slice(&self.dispute_window.to_le_bytes());
+        body[194..202].copy_from_slice(&self.seller_confirmed_at.to_le_bytes());
+        body[202..234].copy_from_slice(self.dispute_raised_by.as_ref());
+        body[234..242].copy_from_slice(&self.disputed_at.to_le_bytes());
+        body[242] = self.arbiter_opt_in;
+        body[243..251].copy_from_slice(&self.ruling_buyer_amount.to_le_bytes());
+        body[251..259].copy_from_slice(&self.ruling_seller_amount.to_le_bytes());
+        body[259] = self.milestone_count;
+        body[260..268].copy_from_slice(&self.released_amount.to_le_bytes());
+        body[268..270].copy_from_slice(&self.settlement_fee_bps.to_le_bytes());
+        body[270] = self.arbiter_fee_mode;
+        body[271..279].copy_from_slice(&self.arbiter_fee.to_le_bytes());
+        body[279..287].copy_from_slice(&self.arbiter_fee_paid.to_le_bytes());
+        body[287..295].copy_from_slice(&self.funded_amount.to_le_bytes());
+        body[295..327].copy_from_slice(self.rent_payer.as_ref());
+        body[327..335].copy_from_slice(&self.held_fee.to_le_bytes());
+        body[335..343].copy_from_slice(&self.seller_bond.to_le_bytes());
+        body[343..351].copy_from_slice(&self.funded_bond.to_le_bytes());
         
         Ok(())
     }
@@ -131,8 +333,674 @@ impl EscrowAccount {
         *pubkey == self.buyer || *pubkey == self.seller || *pubkey == self.arbiter
     }
 
+    /// Party that created the offer: the only one set until someone joins, `None` afterwards.
+    /// Not necessarily the rent payer, who may have covered the fee and rent on its behalf.
+    pub fn initiator(&self) -> Option<Pubkey> {
+        match (self.buyer == Pubkey::default(), self.seller == Pubkey::default()) {
+            (false, true) => Some(self.buyer),
+            (true, false) => Some(self.seller),
+            _ => None,
+        }
+    }
+
+    /// Funding deadline is set and has passed (0 = no deadline)
+    pub fn is_funding_expired(&self, now: i64) -> bool {
+        self.funding_deadline != 0 && now > self.funding_deadline
+    }
+
+    /// Delivery deadline is set and has passed (0 = no deadline)
+    pub fn is_delivery_expired(&self, now: i64) -> bool {
+        self.delivery_deadline != 0 && now > self.delivery_deadline
+    }
+
+    /// Dispute window is set and has elapsed since seller confirmation (0 = no auto-release)
+    pub fn is_dispute_window_elapsed(&self, now: i64) -> bool {
+        self.dispute_window != 0 && now > self.seller_confirmed_at.saturating_add(self.dispute_window)
+    }
+
+    /// Both parties let the arbiter rule without a formal dispute
+    pub fn arbiter_opted_in(&self) -> bool {
+        self.arbiter_opt_in & (ARBITER_OPT_IN_BUYER | ARBITER_OPT_IN_SELLER)
+            == ARBITER_OPT_IN_BUYER | ARBITER_OPT_IN_SELLER
+    }
+
+    /// Arbiter may rule on a disputed escrow, or on an active one if both parties opted in
+    pub fn arbiter_may_act(&self) -> Result<bool, ProgramError> {
+        Ok(match self.get_state()? {
+            EscrowState::Disputed => true,
+            EscrowState::Funded | EscrowState::SellerConfirmed => self.arbiter_opted_in(),
+            _ => false,
+        })
+    }
+
+    pub fn is_milestone_escrow(&self) -> bool {
+        self.milestone_count > 0
+    }
+
+    /// Amount still held in the vault after partial payouts.
+    /// Based on what the vault actually received, which is less than `amount`
+    /// for mints with a transfer fee.
+    pub fn remaining_amount(&self) -> Result<u64, ProgramError> {
+        self.funded_amount
+            .checked_sub(self.released_amount)
+            .ok_or(ProgramError::ArithmeticOverflow)
+    }
+
+    /// A seller bond is required and has not been deposited yet
+    pub fn collateral_due(&self) -> bool {
+        self.seller_bond > 0 && self.funded_bond == 0
+    }
+
+    /// Seller bond still held in the vault; it is paid out when the escrow settles
+    pub fn held_collateral(&self) -> Result<u64, ProgramError> {
+        Ok(if self.get_state()?.is_terminal() { 0 } else { self.funded_bond })
+    }
+
+    /// Arbiter compensation due for ruling over `ruled_amount`.
+    /// A fixed fee is charged once overall; a percentage fee on every ruling.
+    pub fn arbiter_fee_due(&self, ruled_amount: u64) -> Result<u64, ProgramError> {
+        let fee = match self.arbiter_fee_mode {
+            ARBITER_FEE_FIXED => self.arbiter_fee.saturating_sub(self.arbiter_fee_paid),
+            ARBITER_FEE_BPS => {
+                let bps = u16::try_from(self.arbiter_fee).map_err(|_| EscrowError::InvalidArbiterFee)?;
+                AmountHelper::basis_points_of(ruled_amount, bps)?
+            }
+            _ => {
+                msg!("Invalid arbiter fee mode: {}", self.arbiter_fee_mode);
+                return Err(EscrowError::InvalidArbiterFee.into());
+            }
+        };
+        Ok(fee.min(ruled_amount))
+    }
+
     pub fn can_be_closed(&self) -> Result<bool, ProgramError> {
-        let state = self.get_state()?;
-        Ok(state == EscrowState::Completed || state == EscrowState::Cancelled)
+        Ok(self.get_state()?.is_terminal())
+    }
+}
+
+/// Escrow snapshot returned by GetEscrowInfo through `set_return_data`, Borsh-encoded.
+/// Decode it off-chain with `client::decode_escrow_info`.
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct EscrowInfo {
+    /// Escrow fields, read from any layout version
+    pub escrow: EscrowAccount,
+    /// Layout version the account is stored in; below `ESCROW_VERSION` it needs MigrateEscrow
+    pub version: u8,
+    /// Cluster time the snapshot was taken at
+    pub now: i64,
+    /// Lamports in the vault PDA (SOL) or tokens in the vault token account (SPL),
+    /// `None` when the vault accounts were not passed
+    pub vault_balance: Option<u64>,
+    /// Amount still to be paid out of the vault
+    pub remaining_amount: u64,
+    pub funding_expired: bool,
+    pub delivery_expired: bool,
+    /// Time after which the seller may claim an auto-release, for seller-confirmed escrows with a dispute window
+    pub auto_release_after: Option<i64>,
+    pub arbiter_may_act: bool,
+    pub can_be_closed: bool,
+    /// The seller still has to deposit its bond before the buyer may fund
+    pub collateral_due: bool,
+    /// Seller bond held in the vault, on top of `remaining_amount`
+    pub held_collateral: u64,
+}
+
+impl EscrowInfo {
+    pub fn new(escrow: EscrowAccount, version: u8, now: i64, vault_balance: Option<u64>) -> Result<Self, ProgramError> {
+        let auto_release_after = (escrow.get_state()? == EscrowState::SellerConfirmed && escrow.dispute_window != 0)
+            .then(|| escrow.seller_confirmed_at.saturating_add(escrow.dispute_window));
+        Ok(Self {
+            version,
+            now,
+            vault_balance,
+            remaining_amount: if escrow.get_state()?.holds_funds() { escrow.remaining_amount()? } else { 0 },
+            funding_expired: escrow.is_funding_expired(now),
+            delivery_expired: escrow.is_delivery_expired(now),
+            auto_release_after,
+            arbiter_may_act: escrow.arbiter_may_act()?,
+            can_be_closed: escrow.can_be_closed()?,
+            collateral_due: escrow.collateral_due() && !escrow.get_state()?.is_terminal(),
+            held_collateral: escrow.held_collateral()?,
+            escrow,
+        })
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MilestoneStatus {
+    Pending = 0,
+    SellerConfirmed = 1,
+    Released = 2,
+    Refunded = 3,
+}
+
+impl MilestoneStatus {
+    pub fn from_u8(value: u8) -> Result<Self, ProgramError> {
+        match value {
+            0 => Ok(MilestoneStatus::Pending),
+            1 => Ok(MilestoneStatus::SellerConfirmed),
+            2 => Ok(MilestoneStatus::Released),
+            3 => Ok(MilestoneStatus::Refunded),
+            _ => {
+                msg!("Invalid milestone status: {}", value);
+                Err(EscrowError::InvalidAccountLayout.into())
+            }
+        }
+    }
+
+    pub fn is_resolved(&self) -> bool {
+        *self == MilestoneStatus::Released || *self == MilestoneStatus::Refunded
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Milestone {
+    pub amount: u64,
+    pub status: u8,
+}
+
+impl Milestone {
+    pub const LEN: usize = 8 + 1;
+
+    pub fn get_status(&self) -> Result<MilestoneStatus, ProgramError> {
+        MilestoneStatus::from_u8(self.status)
+    }
+
+    pub fn set_status(&mut self, status: MilestoneStatus) {
+        self.status = status as u8;
     }
-}
\ No newline at end of file
+}
+
+/// Staged payout schedule for an escrow, stored in a PDA derived from
+/// `[b"milestones", escrow_key]`
+#[derive(Debug)]
+pub struct MilestoneAccount {
+    pub escrow: Pubkey,
+    pub bump: u8,
+    pub milestones: Vec<Milestone>,
+}
+
+impl MilestoneAccount {
+    pub const MAX_MILESTONES: usize = 16;
+    pub const LEN: usize = DISCRIMINATOR_LEN + 32 + 1 + 1 + Self::MAX_MILESTONES * Milestone::LEN;
+
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        check_discriminator(data, &MILESTONE_DISCRIMINATOR, "milestone")?;
+        if data.len() != Self::LEN {
+            msg!("Invalid milestone account size: expected {}, got {}", Self::LEN, data.len());
+            return Err(EscrowError::InvalidAccountLayout.into());
+        }
+        let data = &data[DISCRIMINATOR_LEN..];
+
+        let escrow = Pubkey::new_from_array(data[0..32].try_into().unwrap());
+        let bump = data[32];
+        let count = data[33] as usize;
+        if count > Self::MAX_MILESTONES {
+            msg!("Invalid milestone count: {}", count);
+            return Err(EscrowError::InvalidAccountLayout.into());
+        }
+
+        let milestones = (0..count)
+            .map(|i| {
+                let offset = 34 + i * Milestone::LEN;
+                Milestone {
+                    amount: u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap()),
+                    status: data[offset + 8],
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            escrow,
+            bump,
+            milestones,
+        })
+    }
+
+    pub fn save_to_account(&self, account: &AccountInfo) -> ProgramResult {
+        let mut data = account.try_borrow_mut_data()?;
+        if data.len() < Self::LEN || self.milestones.len() > Self::MAX_MILESTONES {
+            return Err(EscrowError::InvalidAccountLayout.into());
+        }
+
+        data[..DISCRIMINATOR_LEN].copy_from_slice(&MILESTONE_DISCRIMINATOR);
+        let data = &mut data[DISCRIMINATOR_LEN..];
+
+        data[0..32].copy_from_slice(self.escrow.as_ref());
+        data[32] = self.bump;
+        data[33] = self.milestones.len() as u8;
+        for (i, milestone) in self.milestones.iter().enumerate() {
+            let offset = 34 + i * Milestone::LEN;
+            data[offset..offset + 8].copy_from_slice(&milestone.amount.to_le_bytes());
+            data[offset + 8] = milestone.status;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_mut(&mut self, index: u8) -> Result<&mut Milestone, ProgramError> {
+        self.milestones.get_mut(index as usize).ok_or_else(|| {
+            msg!("Invalid milestone index: {}", index);
+            EscrowError::InvalidMilestoneIndex.into()
+        })
+    }
+
+    /// All milestones are either released or refunded
+    pub fn all_resolved(&self) -> Result<bool, ProgramError> {
+        for milestone in &self.milestones {
+            if !milestone.get_status()?.is_resolved() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// At least one milestone was paid out to the seller
+    pub fn any_released(&self) -> Result<bool, ProgramError> {
+        for milestone in &self.milestones {
+            if milestone.get_status()? == MilestoneStatus::Released {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// At least one milestone was delivered and awaits the buyer's release
+    pub fn any_seller_confirmed(&self) -> Result<bool, ProgramError> {
+        for milestone in &self.milestones {
+            if milestone.get_status()? == MilestoneStatus::SellerConfirmed {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Program-wide settings, stored in a singleton PDA derived from `[b"config"]`
+#[derive(Debug)]
+pub struct ProgramConfig {
+    pub admin: Pubkey,
+    pub fee_collector: Pubkey,
+    pub flat_fee: u64,
+    /// Percentage fee in basis points. SPL escrows pay it at settlement in either mode
+    pub fee_bps: u16,
+    pub bump: u8,
+    /// 0 = percentage fee charged upfront at creation, 1 = deducted from the vault at settlement
+    pub fee_on_settlement: u8,
+    /// Share of the upfront service fee (basis points) refunded when an unjoined offer is cancelled
+    pub cancel_refund_bps: u16,
+}
+
+impl ProgramConfig {
+    pub const LEN: usize = DISCRIMINATOR_LEN + 32 + 32 + 8 + 2 + 1 + 1 + 2;
+
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        check_discriminator(data, &CONFIG_DISCRIMINATOR, "config")?;
+        if data.len() != Self::LEN {
+            msg!("Invalid config account size: expected {}, got {}", Self::LEN, data.len());
+            return Err(EscrowError::InvalidAccountLayout.into());
+        }
+        let data = &data[DISCRIMINATOR_LEN..];
+
+        Ok(Self {
+            admin: Pubkey::new_from_array(data[0..32].try_into().unwrap()),
+            fee_collector: Pubkey::new_from_array(data[32..64].try_into().unwrap()),
+            flat_fee: u64::from_le_bytes(data[64..72].try_into().unwrap()),
+            fee_bps: u16::from_le_bytes(data[72..74].try_into().unwrap()),
+            bump: data[74],
+            fee_on_settlement: data[75],
+            cancel_refund_bps: u16::from_le_bytes(data[76..78].try_into().unwrap()),
+        })
+    }
+
+    pub fn save_to_account(&self, account: &AccountInfo) -> ProgramResult {
+        let mut data = account.try_borrow_mut_data()?;
+        if data.len() < Self::LEN {
+            return Err(EscrowError::InvalidAccountLayout.into());
+        }
+
+        data[..DISCRIMINATOR_LEN].copy_from_slice(&CONFIG_DISCRIMINATOR);
+        let data = &mut data[DISCRIMINATOR_LEN..];
+        data[0..32].copy_from_slice(self.admin.as_ref());
+        data[32..64].copy_from_slice(self.fee_collector.as_ref());
+        data[64..72].copy_from_slice(&self.flat_fee.to_le_bytes());
+        data[72..74].copy_from_slice(&self.fee_bps.to_le_bytes());
+        data[74] = self.bump;
+        data[75] = self.fee_on_settlement;
+        data[76..78].copy_from_slice(&self.cancel_refund_bps.to_le_bytes());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Data `save` writes into a fresh zeroed account of `len` bytes
+    fn saved_data(len: usize, save: impl FnOnce(&AccountInfo) -> ProgramResult) -> Vec<u8> {
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![0u8; len];
+        save(&AccountInfo::new(&key, false, true, &mut lamports, &mut data, &owner, false, 0)).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_milestone_account_round_trip() {
+        let milestones = MilestoneAccount {
+            escrow: Pubkey::new_unique(),
+            bump: 254,
+            milestones: vec![
+                Milestone { amount: 100, status: MilestoneStatus::Released as u8 },
+                Milestone { amount: 250, status: MilestoneStatus::SellerConfirmed as u8 },
+                Milestone { amount: u64::MAX, status: MilestoneStatus::Pending as u8 },
+            ],
+        };
+        let data = saved_data(MilestoneAccount::LEN, |account| milestones.save_to_account(account));
+
+        let loaded = MilestoneAccount::from_account_data(&data).unwrap();
+        assert_eq!(loaded.escrow, milestones.escrow);
+        assert_eq!(loaded.bump, 254);
+        assert_eq!(loaded.milestones, milestones.milestones);
+        assert!(!loaded.all_resolved().unwrap());
+        assert!(loaded.any_released().unwrap());
+    }
+
+    #[test]
+    fn test_escrow_account_round_trip() {
+        let mut escrow = EscrowAccount::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            1_000,
+            253,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        escrow.seller = Pubkey::new_unique();
+        escrow.set_state(EscrowState::Disputed);
+        escrow.funding_deadline = 1_700_000_000;
+        escrow.delivery_deadline = 1_700_086_400;
+        escrow.dispute_window = 3_600;
+        escrow.seller_confirmed_at = 1_700_000_100;
+        escrow.dispute_raised_by = escrow.buyer;
+        escrow.disputed_at = -1;
+        escrow.arbiter_opt_in = ARBITER_OPT_IN_BUYER;
+        escrow.ruling_buyer_amount = 400;
+        escrow.ruling_seller_amount = 590;
+        escrow.milestone_count = 3;
+        escrow.released_amount = 10;
+        escrow.settlement_fee_bps = 250;
+        escrow.arbiter_fee_mode = ARBITER_FEE_BPS;
+        escrow.arbiter_fee = 100;
+        escrow.arbiter_fee_paid = 9;
+        escrow.funded_amount = 990;
+        escrow.rent_payer = Pubkey::new_unique();
+        escrow.held_fee = 5_000;
+        escrow.seller_bond = 200;
+        escrow.funded_bond = 198;
+        let data = saved_data(EscrowAccount::LEN, |account| escrow.save_to_account(account));
+
+        let loaded = EscrowAccount::from_account_data(&data).unwrap();
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", escrow));
+        // Borsh and the account layout agree
+        assert_eq!(borsh::to_vec(&escrow).unwrap(), data[EscrowAccount::HEADER_LEN..]);
+    }
+
+    #[test]
+    fn test_escrow_info() {
+        let mut escrow = EscrowAccount::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            1_000,
+            255,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        escrow.seller = Pubkey::new_unique();
+        escrow.set_state(EscrowState::SellerConfirmed);
+        escrow.funding_deadline = 1_000;
+        escrow.delivery_deadline = 5_000;
+        escrow.dispute_window = 600;
+        escrow.seller_confirmed_at = 2_000;
+        escrow.funded_amount = 990;
+        escrow.released_amount = 90;
+
+        escrow.seller_bond = 100;
+        escrow.funded_bond = 100;
+
+        let info = EscrowInfo::new(escrow.clone(), ESCROW_VERSION, 2_500, Some(1_000)).unwrap();
+        assert_eq!(info.remaining_amount, 900);
+        assert_eq!(info.held_collateral, 100);
+        assert!(!info.collateral_due);
+        assert!(info.funding_expired);
+        assert!(!info.delivery_expired);
+        assert_eq!(info.auto_release_after, Some(2_600));
+        assert!(!info.arbiter_may_act);
+        assert!(!info.can_be_closed);
+        assert_eq!(EscrowInfo::try_from_slice(&borsh::to_vec(&info).unwrap()).unwrap(), info);
+
+        escrow.set_state(EscrowState::Disputed);
+        let mut info = EscrowInfo::new(escrow, 1, 6_000, None).unwrap();
+        assert!(info.delivery_expired);
+        assert_eq!(info.auto_release_after, None);
+        assert!(info.arbiter_may_act);
+        assert_eq!(info.version, 1);
+        assert_eq!(info.remaining_amount, 900);
+
+        // Single-payment escrows pay out without recording a released amount
+        info.escrow.set_state(EscrowState::Completed);
+        let info = EscrowInfo::new(info.escrow, ESCROW_VERSION, 6_000, Some(0)).unwrap();
+        assert_eq!(info.remaining_amount, 0);
+        assert_eq!(info.held_collateral, 0);
+        assert!(info.can_be_closed);
+
+        // Joined, but the seller has not deposited its bond yet
+        let mut escrow = info.escrow;
+        escrow.set_state(EscrowState::Initialized);
+        escrow.funded_amount = 0;
+        escrow.funded_bond = 0;
+        let info = EscrowInfo::new(escrow, ESCROW_VERSION, 0, Some(0)).unwrap();
+        assert!(info.collateral_due);
+        assert_eq!(info.held_collateral, 0);
+    }
+
+    #[test]
+    fn test_program_config_round_trip() {
+        let config = ProgramConfig {
+            admin: Pubkey::new_unique(),
+            fee_collector: Pubkey::new_unique(),
+            flat_fee: 5_000_000,
+            fee_bps: 150,
+            bump: 252,
+            fee_on_settlement: 0,
+            cancel_refund_bps: 7_500,
+        };
+        let data = saved_data(ProgramConfig::LEN, |account| config.save_to_account(account));
+
+        let loaded = ProgramConfig::from_account_data(&data).unwrap();
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", config));
+    }
+
+    fn sample_escrow() -> EscrowAccount {
+        let mut escrow = EscrowAccount::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            1_000,
+            251,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        escrow.seller = Pubkey::new_unique();
+        escrow.set_state(EscrowState::Funded);
+        escrow.funding_deadline = 1_700_000_000;
+        escrow.delivery_deadline = 1_700_086_400;
+        escrow.dispute_window = 3_600;
+        escrow.seller_confirmed_at = 1_700_000_100;
+        escrow.dispute_raised_by = escrow.seller;
+        escrow.disputed_at = 1_700_000_200;
+        escrow.arbiter_opt_in = ARBITER_OPT_IN_SELLER;
+        escrow.ruling_buyer_amount = 300;
+        escrow.ruling_seller_amount = 700;
+        escrow.milestone_count = 2;
+        escrow.released_amount = 100;
+        escrow.settlement_fee_bps = 50;
+        escrow.arbiter_fee_mode = ARBITER_FEE_FIXED;
+        escrow.arbiter_fee = 20;
+        escrow.arbiter_fee_paid = 20;
+        escrow.funded_amount = 1_000;
+        escrow.rent_payer = Pubkey::new_unique();
+        escrow.held_fee = 7;
+        escrow.seller_bond = 50;
+        escrow.funded_bond = 50;
+        escrow
+    }
+
+    #[test]
+    fn test_legacy_layout_round_trip() {
+        let escrow = sample_escrow();
+        let current = saved_data(EscrowAccount::LEN, |account| escrow.save_to_account(account));
+        assert_eq!(current[..DISCRIMINATOR_LEN], ESCROW_DISCRIMINATOR);
+        assert_eq!(current[DISCRIMINATOR_LEN], ESCROW_VERSION);
+        let body = &current[EscrowAccount::HEADER_LEN..];
+
+        // An unversioned account is the current body cut at its size
+        let legacy = body[..LEGACY_ESCROW_LEN].to_vec();
+        assert_eq!(
+            EscrowAccount::from_account_data(&legacy).err(),
+            Some(EscrowError::AccountNeedsMigration.into())
+        );
+
+        let (loaded, version) = EscrowAccount::from_any_version(&legacy).unwrap();
+        assert_eq!(version, 0);
+
+        // Migrate and read back through the strict loader
+        let migrated = saved_data(EscrowAccount::LEN, |account| loaded.save_to_account(account));
+        let reloaded = EscrowAccount::from_account_data(&migrated).unwrap();
+        assert_eq!(reloaded, loaded);
+
+        // Every field the legacy layout had survives unchanged
+        assert_eq!(migrated[EscrowAccount::HEADER_LEN..EscrowAccount::HEADER_LEN + LEGACY_ESCROW_LEN], legacy[..]);
+
+        // Fields it lacked are defaulted
+        assert_eq!(reloaded.funded_amount, reloaded.amount);
+        assert_eq!(reloaded.rent_payer, escrow.buyer);
+        assert_eq!(reloaded.funding_deadline, 0);
+        assert_eq!(reloaded.held_fee, 0);
+        assert_eq!((reloaded.seller_bond, reloaded.funded_bond), (0, 0));
+
+        // Any other unversioned size is not an escrow layout
+        assert!(EscrowAccount::from_any_version(&body[..LEGACY_ESCROW_LEN + 16]).is_err());
+    }
+
+    #[test]
+    fn test_current_layout_round_trip() {
+        let escrow = sample_escrow();
+        let mut data = saved_data(EscrowAccount::LEN, |account| escrow.save_to_account(account));
+
+        let (loaded, version) = EscrowAccount::from_any_version(&data).unwrap();
+        assert_eq!(version, ESCROW_VERSION);
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", escrow));
+
+        // Unknown version byte or size is rejected
+        data[DISCRIMINATOR_LEN] = ESCROW_VERSION + 1;
+        assert!(EscrowAccount::from_account_data(&data).is_err());
+        assert!(EscrowAccount::from_any_version(&data).is_err());
+        assert!(EscrowAccount::from_any_version(&data[..200]).is_err());
+    }
+
+    #[test]
+    fn test_legacy_unfunded_escrow_defaults() {
+        let mut escrow = sample_escrow();
+        escrow.buyer = Pubkey::default();
+        escrow.set_state(EscrowState::Created);
+        let data = saved_data(EscrowAccount::LEN, |account| escrow.save_to_account(account));
+
+        let legacy = &data[EscrowAccount::HEADER_LEN..EscrowAccount::HEADER_LEN + LEGACY_ESCROW_LEN];
+        let (loaded, _) = EscrowAccount::from_any_version(legacy).unwrap();
+        assert_eq!(loaded.funded_amount, 0);
+        assert_eq!(loaded.rent_payer, escrow.seller);
+    }
+
+    #[test]
+    fn test_discriminators() {
+        use solana_program::hash::hash;
+        for (name, discriminator) in [
+            ("EscrowAccount", ESCROW_DISCRIMINATOR),
+            ("MilestoneAccount", MILESTONE_DISCRIMINATOR),
+            ("ProgramConfig", CONFIG_DISCRIMINATOR),
+        ] {
+            let expected = hash(format!("account:{}", name).as_bytes()).to_bytes();
+            assert_eq!(discriminator[..], expected[..DISCRIMINATOR_LEN], "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_loaders_reject_other_account_types() {
+        let discriminator_error = Err(EscrowError::InvalidAccountDiscriminator.into());
+        let key = Pubkey::new_unique();
+
+        let escrow_bytes = saved_data(EscrowAccount::LEN, |account| sample_escrow().save_to_account(account));
+        let milestone = MilestoneAccount { escrow: key, bump: 255, milestones: vec![] };
+        let milestone_bytes = saved_data(MilestoneAccount::LEN, |account| milestone.save_to_account(account));
+        let config = ProgramConfig {
+            admin: key,
+            fee_collector: key,
+            flat_fee: 0,
+            fee_bps: 0,
+            bump: 255,
+            fee_on_settlement: 0,
+            cancel_refund_bps: 0,
+        };
+        let config_bytes = saved_data(ProgramConfig::LEN, |account| config.save_to_account(account));
+
+        assert_eq!(MilestoneAccount::from_account_data(&escrow_bytes).map(|_| ()), discriminator_error);
+        assert_eq!(ProgramConfig::from_account_data(&escrow_bytes).map(|_| ()), discriminator_error);
+        assert_eq!(EscrowAccount::from_account_data(&config_bytes).map(|_| ()), discriminator_error);
+        assert_eq!(MilestoneAccount::from_account_data(&config_bytes).map(|_| ()), discriminator_error);
+        assert_eq!(ProgramConfig::from_account_data(&milestone_bytes).map(|_| ()), discriminator_error);
+        assert_eq!(EscrowAccount::from_account_data(&milestone_bytes).map(|_| ()), discriminator_error);
+
+        // Closed (zeroed) accounts are rejected too
+        assert_eq!(ProgramConfig::from_account_data(&[0u8; ProgramConfig::LEN]).map(|_| ()), discriminator_error);
+    }
+
+    #[test]
+    fn test_remaining_amount_uses_received_amount() {
+        let mut escrow = EscrowAccount::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            1_000,
+            255,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        // 1% transfer fee withheld on the way into the vault
+        escrow.funded_amount = 990;
+        escrow.released_amount = 90;
+        assert_eq!(escrow.remaining_amount().unwrap(), 900);
+
+        escrow.released_amount = 991;
+        assert!(escrow.remaining_amount().is_err());
+    }
+
+    #[test]
+    fn test_milestones_resolved() {
+        let mut milestones = MilestoneAccount {
+            escrow: Pubkey::new_unique(),
+            bump: 255,
+            milestones: vec![
+                Milestone { amount: 1, status: MilestoneStatus::Refunded as u8 },
+                Milestone { amount: 2, status: MilestoneStatus::Pending as u8 },
+            ],
+        };
+        assert!(!milestones.all_resolved().unwrap());
+        assert!(milestones.get_mut(2).is_err());
+
+        milestones.get_mut(1).unwrap().set_status(MilestoneStatus::Refunded);
+        assert!(milestones.all_resolved().unwrap());
+        assert!(!milestones.any_released().unwrap());
+    }
+}
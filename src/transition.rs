@@ -0,0 +1,519 @@
+//! Escrow state machine: which action may move an escrow from one state to the next,
+//! and who may trigger it.
+//!
+//! Handlers resolve the signer's roles and the time-dependent conditions from the escrow
+//! account, call [`transition`] before moving any funds and store the state it returns.
+//! The function itself touches no accounts.
+
+use solana_program::{msg, pubkey::Pubkey};
+
+use crate::{
+    instructions::EscrowError,
+    state::{EscrowAccount, EscrowState},
+};
+
+/// State-changing actions, one per instruction handler
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    JoinOffer,
+    InitMilestones,
+    CancelOffer,
+    FundCollateral,
+    FundEscrow,
+    SellerConfirm,
+    ConfirmEscrow,
+    ArbiterConfirm,
+    ArbiterCancel,
+    /// Split ruling; the escrow completes if the seller receives anything
+    ArbiterSettle { seller_paid: bool },
+    RaiseDispute,
+    MutualCancel,
+    ClaimExpiredRefund,
+    ClaimAutoRelease,
+    ConfirmMilestone,
+    ReleaseMilestone,
+    ArbiterRuleMilestone,
+    /// Last milestone resolved; the escrow completes if any milestone was released
+    FinishMilestones { any_released: bool },
+    CloseEscrow,
+}
+
+/// Roles held by the signer(s) of an instruction. One key may hold several.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Actor {
+    pub buyer: bool,
+    pub seller: bool,
+    pub arbiter: bool,
+    /// Created the offer, while nobody has joined it
+    pub initiator: bool,
+}
+
+impl Actor {
+    /// No role at all, as for permissionless cranks
+    pub const ANYONE: Self = Self {
+        buyer: false,
+        seller: false,
+        arbiter: false,
+        initiator: false,
+    };
+
+    pub fn of(escrow: &EscrowAccount, key: &Pubkey) -> Self {
+        Self {
+            buyer: escrow.buyer == *key,
+            seller: escrow.seller == *key,
+            arbiter: escrow.arbiter == *key,
+            initiator: escrow.initiator() == Some(*key),
+        }
+    }
+
+    /// Roles of two co-signers together
+    pub fn and(self, other: Self) -> Self {
+        Self {
+            buyer: self.buyer || other.buyer,
+            seller: self.seller || other.seller,
+            arbiter: self.arbiter || other.arbiter,
+            initiator: self.initiator || other.initiator,
+        }
+    }
+}
+
+/// Facts about the escrow, other than its state, that transitions depend on
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Conditions {
+    pub funding_expired: bool,
+    pub delivery_expired: bool,
+    pub dispute_window_elapsed: bool,
+    /// Both parties let the arbiter rule without a dispute
+    pub arbiter_opted_in: bool,
+    pub milestone_escrow: bool,
+    /// A seller bond is required and not deposited yet
+    pub collateral_due: bool,
+}
+
+impl Conditions {
+    pub fn at(escrow: &EscrowAccount, now: i64) -> Self {
+        Self {
+            funding_expired: escrow.is_funding_expired(now),
+            delivery_expired: escrow.is_delivery_expired(now),
+            dispute_window_elapsed: escrow.is_dispute_window_elapsed(now),
+            arbiter_opted_in: escrow.arbiter_opted_in(),
+            milestone_escrow: escrow.is_milestone_escrow(),
+            collateral_due: escrow.collateral_due(),
+        }
+    }
+}
+
+/// State reached by `actor` performing `action` on an escrow in `state`.
+/// Terminal states are absorbing: only `CloseEscrow` is accepted there, and it keeps the state.
+pub fn transition(
+    state: EscrowState,
+    action: Action,
+    actor: Actor,
+    conditions: &Conditions,
+) -> Result<EscrowState, EscrowError> {
+    use EscrowState::*;
+
+    match action {
+        Action::JoinOffer => {
+            expect_state(state, &[Created])?;
+            if conditions.funding_expired {
+                msg!("Funding deadline has passed");
+                return Err(EscrowError::DeadlinePassed);
+            }
+            Ok(Initialized)
+        }
+        Action::InitMilestones => {
+            expect_state(state, &[Created])?;
+            require(actor.initiator, "initiator", EscrowError::NotInitiator)?;
+            Ok(Created)
+        }
+        Action::CancelOffer => {
+            expect_state(state, &[Created])?;
+            require(actor.initiator, "initiator", EscrowError::NotInitiator)?;
+            Ok(Cancelled)
+        }
+        Action::FundCollateral => {
+            expect_state(state, &[Initialized])?;
+            if conditions.funding_expired {
+                msg!("Funding deadline has passed");
+                return Err(EscrowError::DeadlinePassed);
+            }
+            require(actor.seller, "seller", EscrowError::NotSeller)?;
+            if !conditions.collateral_due {
+                msg!("Escrow requires no seller collateral, or it was already deposited");
+                return Err(EscrowError::NoCollateralDue);
+            }
+            Ok(Initialized)
+        }
+        Action::FundEscrow => {
+            expect_state(state, &[Initialized])?;
+            if conditions.funding_expired {
+                msg!("Funding deadline has passed");
+                return Err(EscrowError::DeadlinePassed);
+            }
+            require(actor.buyer, "buyer", EscrowError::NotBuyer)?;
+            if conditions.collateral_due {
+                msg!("Seller must deposit the collateral first");
+                return Err(EscrowError::CollateralNotFunded);
+            }
+            Ok(Funded)
+        }
+        Action::SellerConfirm => {
+            expect_state(state, &[Funded])?;
+            if conditions.delivery_expired {
+                msg!("Delivery deadline has passed");
+                return Err(EscrowError::DeadlinePassed);
+            }
+            require(actor.seller, "seller", EscrowError::NotSeller)?;
+            if conditions.milestone_escrow {
+                msg!("Milestone escrows are confirmed per milestone");
+                return Err(EscrowError::MilestoneEscrow);
+            }
+            Ok(SellerConfirmed)
+        }
+        Action::ConfirmEscrow => {
+            expect_state(state, &[SellerConfirmed])?;
+            require(actor.buyer, "buyer", EscrowError::NotBuyer)?;
+            Ok(Completed)
+        }
+        Action::ArbiterConfirm => {
+            arbitrate(state, actor, conditions)?;
+            Ok(Completed)
+        }
+        Action::ArbiterCancel => {
+            arbitrate(state, actor, conditions)?;
+            Ok(Cancelled)
+        }
+        Action::ArbiterSettle { seller_paid } => {
+            arbitrate(state, actor, conditions)?;
+            Ok(if seller_paid { Completed } else { Cancelled })
+        }
+        Action::RaiseDispute => {
+            expect_state(state, &[Funded, SellerConfirmed])?;
+            require(actor.buyer || actor.seller, "buyer or seller", EscrowError::NotBuyerOrSeller)?;
+            Ok(Disputed)
+        }
+        Action::MutualCancel => {
+            require(actor.buyer, "buyer", EscrowError::NotBuyer)?;
+            require(actor.seller, "seller", EscrowError::NotSeller)?;
+            expect_state(state, &[Initialized, Funded, Disputed])?;
+            Ok(Cancelled)
+        }
+        Action::ClaimExpiredRefund => {
+            // Funded escrows refund after the delivery deadline, unfunded offers expire after the funding one
+            expect_state(state, &[Created, Initialized, Funded])?;
+            let expired = if state == Funded {
+                conditions.delivery_expired
+            } else {
+                conditions.funding_expired
+            };
+            if !expired {
+                msg!("Deadline not reached");
+                return Err(EscrowError::DeadlineNotReached);
+            }
+            Ok(Cancelled)
+        }
+        Action::ClaimAutoRelease => {
+            expect_state(state, &[SellerConfirmed])?;
+            if !conditions.dispute_window_elapsed {
+                msg!("Dispute window not elapsed");
+                return Err(EscrowError::DisputeWindowActive);
+            }
+            Ok(Completed)
+        }
+        Action::ConfirmMilestone => {
+            expect_state(state, &[Funded])?;
+            if conditions.delivery_expired {
+                msg!("Delivery deadline has passed");
+                return Err(EscrowError::DeadlinePassed);
+            }
+            require(actor.seller, "seller", EscrowError::NotSeller)?;
+            Ok(Funded)
+        }
+        Action::ReleaseMilestone => {
+            expect_state(state, &[Funded])?;
+            require(actor.buyer, "buyer", EscrowError::NotBuyer)?;
+            Ok(Funded)
+        }
+        Action::ArbiterRuleMilestone => {
+            arbitrate(state, actor, conditions)?;
+            Ok(state)
+        }
+        Action::FinishMilestones { any_released } => {
+            expect_state(state, &[Funded, SellerConfirmed, Disputed])?;
+            Ok(if any_released { Completed } else { Cancelled })
+        }
+        Action::CloseEscrow => {
+            expect_state(state, &[Completed, Cancelled])?;
+            Ok(state)
+        }
+    }
+}
+
+fn expect_state(state: EscrowState, allowed: &[EscrowState]) -> Result<(), EscrowError> {
+    if !allowed.contains(&state) {
+        msg!("Escrow must be in one of {:?}, is {:?}", allowed, state);
+        return Err(EscrowError::InvalidState);
+    }
+    Ok(())
+}
+
+fn require(has_role: bool, role: &str, error: EscrowError) -> Result<(), EscrowError> {
+    if !has_role {
+        msg!("Signer is not the {}", role);
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Arbiter may rule on a disputed escrow, or on an active one if both parties opted in
+fn arbitrate(state: EscrowState, actor: Actor, conditions: &Conditions) -> Result<(), EscrowError> {
+    require(actor.arbiter, "arbiter", EscrowError::NotArbiter)?;
+    let may_act = match state {
+        EscrowState::Disputed => true,
+        EscrowState::Funded | EscrowState::SellerConfirmed => conditions.arbiter_opted_in,
+        _ => false,
+    };
+    if !may_act {
+        msg!("Escrow must be disputed, or both parties must opt in to arbitration");
+        return Err(EscrowError::ArbitrationNotAllowed);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use EscrowState::*;
+
+    fn action() -> impl Strategy<Value = Action> {
+        prop_oneof![
+            Just(Action::JoinOffer),
+            Just(Action::InitMilestones),
+            Just(Action::CancelOffer),
+            Just(Action::FundCollateral),
+            Just(Action::FundEscrow),
+            Just(Action::SellerConfirm),
+            Just(Action::ConfirmEscrow),
+            Just(Action::ArbiterConfirm),
+            Just(Action::ArbiterCancel),
+            any::<bool>().prop_map(|seller_paid| Action::ArbiterSettle { seller_paid }),
+            Just(Action::RaiseDispute),
+            Just(Action::MutualCancel),
+            Just(Action::ClaimExpiredRefund),
+            Just(Action::ClaimAutoRelease),
+            Just(Action::ConfirmMilestone),
+            Just(Action::ReleaseMilestone),
+            Just(Action::ArbiterRuleMilestone),
+            any::<bool>().prop_map(|any_released| Action::FinishMilestones { any_released }),
+            Just(Action::CloseEscrow),
+        ]
+    }
+
+    fn actor() -> impl Strategy<Value = Actor> {
+        any::<[bool; 4]>().prop_map(|[buyer, seller, arbiter, initiator]| Actor {
+            buyer,
+            seller,
+            arbiter,
+            initiator,
+        })
+    }
+
+    fn conditions() -> impl Strategy<Value = Conditions> {
+        any::<[bool; 6]>().prop_map(
+            |[funding_expired, delivery_expired, dispute_window_elapsed, arbiter_opted_in, milestone_escrow, collateral_due]| {
+                Conditions {
+                    funding_expired,
+                    delivery_expired,
+                    dispute_window_elapsed,
+                    arbiter_opted_in,
+                    milestone_escrow,
+                    collateral_due,
+                }
+            },
+        )
+    }
+
+    /// Who may trigger each action, written down independently of `transition`
+    fn authorized(action: Action, actor: Actor) -> bool {
+        match action {
+            Action::InitMilestones | Action::CancelOffer => actor.initiator,
+            Action::FundEscrow | Action::ConfirmEscrow | Action::ReleaseMilestone => actor.buyer,
+            Action::FundCollateral | Action::SellerConfirm | Action::ConfirmMilestone => actor.seller,
+            Action::ArbiterConfirm
+            | Action::ArbiterCancel
+            | Action::ArbiterSettle { .. }
+            | Action::ArbiterRuleMilestone => actor.arbiter,
+            Action::RaiseDispute => actor.buyer || actor.seller,
+            Action::MutualCancel => actor.buyer && actor.seller,
+            Action::JoinOffer
+            | Action::ClaimExpiredRefund
+            | Action::ClaimAutoRelease
+            | Action::FinishMilestones { .. }
+            | Action::CloseEscrow => true,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_random_action_sequences(
+            steps in prop::collection::vec((action(), actor(), conditions()), 1..64)
+        ) {
+            let mut state = Created;
+            // Model of the vault: whether it holds the escrowed amount, and how often it was paid out
+            let mut vault_funded = false;
+            let mut payouts = 0;
+
+            for (action, actor, conditions) in steps {
+                let Ok(next) = transition(state, action, actor, &conditions) else {
+                    continue;
+                };
+
+                prop_assert!(authorized(action, actor), "{:?} accepted from {:?}", action, actor);
+                // Nobody joins or funds an offer past its funding deadline
+                if matches!(action, Action::JoinOffer | Action::FundEscrow | Action::FundCollateral) {
+                    prop_assert!(!conditions.funding_expired, "{:?} accepted after the funding deadline", action);
+                }
+                // The buyer funds only once the seller bond is in, and the bond goes in only once
+                match action {
+                    Action::FundEscrow => prop_assert!(!conditions.collateral_due),
+                    Action::FundCollateral => {
+                        prop_assert!(conditions.collateral_due);
+                        prop_assert_eq!((state, next), (Initialized, Initialized));
+                    }
+                    _ => {}
+                }
+                if state.is_terminal() {
+                    prop_assert_eq!(action, Action::CloseEscrow);
+                    prop_assert_eq!(next, state);
+                }
+
+                // Funds enter the vault by funding only, and leave it in a single settlement
+                if !state.holds_funds() && next.holds_funds() {
+                    prop_assert_eq!(action, Action::FundEscrow);
+                    prop_assert!(!vault_funded);
+                    vault_funded = true;
+                }
+                if state.holds_funds() && !next.holds_funds() {
+                    prop_assert!(vault_funded);
+                    prop_assert!(next.is_terminal());
+                    vault_funded = false;
+                    payouts += 1;
+                }
+                prop_assert!(payouts <= 1, "vault paid out {} times", payouts);
+                prop_assert_eq!(vault_funded, next.holds_funds());
+
+                state = next;
+            }
+        }
+
+        #[test]
+        fn test_terminal_states_are_absorbing(
+            state in prop_oneof![Just(Completed), Just(Cancelled)],
+            action in action(),
+            actor in actor(),
+            conditions in conditions(),
+        ) {
+            match transition(state, action, actor, &conditions) {
+                Ok(next) => {
+                    prop_assert_eq!(action, Action::CloseEscrow);
+                    prop_assert_eq!(next, state);
+                }
+                Err(error) => prop_assert!(
+                    matches!(error, EscrowError::InvalidState | EscrowError::ArbitrationNotAllowed)
+                        || !authorized(action, actor),
+                    "{:?} on {:?} failed with {:?}", action, state, error
+                ),
+            }
+        }
+
+        #[test]
+        fn test_unauthorized_actor_rejected(
+            state in prop_oneof![Just(Created), Just(Initialized), Just(Funded), Just(SellerConfirmed), Just(Disputed)],
+            action in action(),
+            actor in actor(),
+            conditions in conditions(),
+        ) {
+            prop_assume!(!authorized(action, actor));
+            prop_assert!(transition(state, action, actor, &conditions).is_err());
+        }
+    }
+
+    #[test]
+    fn test_lifecycle_and_error_precedence() {
+        let buyer = Actor { buyer: true, initiator: true, ..Actor::ANYONE };
+        let seller = Actor { seller: true, ..Actor::ANYONE };
+        let arbiter = Actor { arbiter: true, ..Actor::ANYONE };
+        let now = Conditions::default();
+
+        let mut state = Created;
+        for (action, actor, expected) in [
+            (Action::JoinOffer, seller, Initialized),
+            (Action::FundEscrow, buyer, Funded),
+            (Action::SellerConfirm, seller, SellerConfirmed),
+            (Action::RaiseDispute, buyer, Disputed),
+            (Action::ArbiterSettle { seller_paid: false }, arbiter, Cancelled),
+            (Action::CloseEscrow, Actor::ANYONE, Cancelled),
+        ] {
+            state = transition(state, action, actor, &now).unwrap();
+            assert_eq!(state, expected);
+        }
+
+        // Role is checked before arbitration is allowed, state before role elsewhere
+        assert_eq!(transition(Funded, Action::ArbiterConfirm, seller, &now), Err(EscrowError::NotArbiter));
+        assert_eq!(
+            transition(Funded, Action::ArbiterConfirm, arbiter, &now),
+            Err(EscrowError::ArbitrationNotAllowed)
+        );
+        let opted_in = Conditions { arbiter_opted_in: true, ..now };
+        assert_eq!(transition(Funded, Action::ArbiterConfirm, arbiter, &opted_in), Ok(Completed));
+        assert_eq!(transition(Funded, Action::FundEscrow, seller, &now), Err(EscrowError::InvalidState));
+        assert_eq!(transition(Initialized, Action::FundEscrow, seller, &now), Err(EscrowError::NotBuyer));
+
+        let bond_due = Conditions { collateral_due: true, ..now };
+        assert_eq!(transition(Initialized, Action::FundEscrow, buyer, &bond_due), Err(EscrowError::CollateralNotFunded));
+        assert_eq!(transition(Initialized, Action::FundCollateral, seller, &bond_due), Ok(Initialized));
+        assert_eq!(transition(Initialized, Action::FundCollateral, buyer, &bond_due), Err(EscrowError::NotSeller));
+        assert_eq!(transition(Initialized, Action::FundCollateral, seller, &now), Err(EscrowError::NoCollateralDue));
+        assert_eq!(transition(Funded, Action::FundCollateral, seller, &bond_due), Err(EscrowError::InvalidState));
+
+        let expired = Conditions { funding_expired: true, delivery_expired: true, ..now };
+        assert_eq!(transition(Initialized, Action::FundEscrow, buyer, &expired), Err(EscrowError::DeadlinePassed));
+        assert_eq!(transition(Created, Action::JoinOffer, seller, &expired), Err(EscrowError::DeadlinePassed));
+        assert_eq!(
+            transition(Funded, Action::ClaimExpiredRefund, Actor::ANYONE, &now),
+            Err(EscrowError::DeadlineNotReached)
+        );
+        assert_eq!(transition(Funded, Action::ClaimExpiredRefund, Actor::ANYONE, &expired), Ok(Cancelled));
+    }
+
+    #[test]
+    fn test_initiator_is_the_offer_creator() {
+        let (seller, payer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut escrow = EscrowAccount::new(
+            &Pubkey::default(),
+            &Pubkey::new_unique(),
+            1_000,
+            255,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        escrow.seller = seller;
+        escrow.rent_payer = payer;
+
+        // A separate payer covers the rent but may not act for the initiator
+        assert!(Actor::of(&escrow, &seller).initiator);
+        assert!(!Actor::of(&escrow, &payer).initiator);
+        let now = Conditions::at(&escrow, 0);
+        assert_eq!(transition(Created, Action::CancelOffer, Actor::of(&escrow, &seller), &now), Ok(Cancelled));
+        assert_eq!(
+            transition(Created, Action::InitMilestones, Actor::of(&escrow, &payer), &now),
+            Err(EscrowError::NotInitiator)
+        );
+
+        // Nobody is the initiator once the offer is joined
+        escrow.buyer = Pubkey::new_unique();
+        assert!(!Actor::of(&escrow, &seller).initiator);
+    }
+}
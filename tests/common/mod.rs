@@ -0,0 +1,448 @@
+//! Shared setup for the program-test suites: a bank with the escrow program, an
+//! initialized ProgramConfig and funded buyer, seller and arbiter wallets.
+
+#![allow(dead_code)]
+
+use std::{collections::HashSet, sync::Once};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestBanksClientExt, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    account_info::AccountInfo,
+    bpf_loader_upgradeable,
+    clock::Clock,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    native_token::LAMPORTS_PER_SOL,
+    program_pack::Pack,
+    program_stubs::{self, SyscallStubs},
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signature, Signer},
+    system_instruction, system_program,
+    transaction::{Transaction, TransactionError},
+};
+
+use solana_smart_contract::{
+    client::{self, EscrowKeys},
+    events::{self, EscrowEvent},
+    instructions::{ConfigArgs, CreateOfferArgs, EscrowError},
+    pda,
+    process_instruction,
+    state::{EscrowAccount, EscrowInfo, EscrowState},
+    utils::{AccountHelper, ASSOCIATED_TOKEN_PROGRAM_ID, NATIVE_MINT, SPL_TOKEN_PROGRAM_ID},
+};
+
+pub const FLAT_FEE: u64 = 10_000_000;
+pub const AMOUNT: u64 = 2 * LAMPORTS_PER_SOL;
+pub const TOKEN_AMOUNT: u64 = 1_000_000;
+pub const DECIMALS: u8 = 6;
+const INITIAL_BALANCE: u64 = 10 * LAMPORTS_PER_SOL;
+
+pub struct TestEnv {
+    pub context: ProgramTestContext,
+    pub program_id: Pubkey,
+    pub admin: Keypair,
+    pub buyer: Keypair,
+    pub seller: Keypair,
+    pub arbiter: Keypair,
+    pub outsider: Keypair,
+    pub fee_collector: Pubkey,
+    /// Signatures already submitted; resending one would be rejected as a duplicate
+    sent: HashSet<Signature>,
+}
+
+pub fn system_account(lamports: u64) -> Account {
+    Account::new(lamports, 0, &system_program::id())
+}
+
+/// ProgramData account naming `authority` as upgrade authority
+fn program_data_account(authority: &Pubkey) -> Account {
+    let mut data = vec![0u8; 45];
+    data[0..4].copy_from_slice(&3u32.to_le_bytes());
+    data[12] = 1;
+    data[13..45].copy_from_slice(authority.as_ref());
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: bpf_loader_upgradeable::id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+impl TestEnv {
+    /// Start a bank and initialize the config with an upfront flat fee
+    pub async fn start() -> Self {
+        Self::start_with(|_| {}).await
+    }
+
+    /// Like `start`, with `setup` adding programs or accounts to the bank first
+    pub async fn start_with(setup: impl FnOnce(&mut ProgramTest)) -> Self {
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new("solana_smart_contract", program_id, processor!(process_instruction));
+        setup(&mut program_test);
+
+        let admin = Keypair::new();
+        let buyer = Keypair::new();
+        let seller = Keypair::new();
+        let arbiter = Keypair::new();
+        let outsider = Keypair::new();
+        let fee_collector = Pubkey::new_unique();
+        for wallet in [&admin, &buyer, &seller, &arbiter, &outsider] {
+            program_test.add_account(wallet.pubkey(), system_account(INITIAL_BALANCE));
+        }
+        program_test.add_account(fee_collector, system_account(LAMPORTS_PER_SOL));
+        program_test.add_account(pda::find_program_data_address(&program_id), program_data_account(&admin.pubkey()));
+
+        let context = program_test.start_with_context().await;
+        // After program-test installed its stubs, before any test sends a transaction
+        static LOG_DATA: Once = Once::new();
+        LOG_DATA.call_once(|| {
+            let stubs = program_stubs::set_syscall_stubs(Box::new(NoStubs));
+            program_stubs::set_syscall_stubs(Box::new(LogDataStubs(stubs)));
+        });
+
+        let mut env = Self {
+            context,
+            program_id,
+            admin,
+            buyer,
+            seller,
+            arbiter,
+            outsider,
+            fee_collector,
+            sent: HashSet::new(),
+        };
+
+        let config = ConfigArgs {
+            fee_collector,
+            flat_fee: FLAT_FEE,
+            fee_bps: 0,
+            fee_on_settlement: 0,
+            cancel_refund_bps: 0,
+        };
+        let ix = client::initialize_config_ix(&program_id, &env.admin.pubkey(), config);
+        let admin = env.admin.insecure_clone();
+        env.process(&[ix], &[&admin]).await.unwrap();
+        env
+    }
+
+    /// Send `instructions` paid by the bank payer, so signer balances only move by program effects
+    pub async fn process(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> Result<(), BanksClientError> {
+        let transaction = self.transaction(instructions, signers).await;
+        self.context.banks_client.process_transaction(transaction).await
+    }
+
+    /// Send `instructions` and return the program's log messages
+    pub async fn process_logs(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> Vec<String> {
+        let transaction = self.transaction(instructions, signers).await;
+        let result = self.context.banks_client.process_transaction_with_metadata(transaction).await.unwrap();
+        result.result.unwrap();
+        let logs = result.metadata.unwrap().log_messages;
+        logs.into_iter()
+            .map(|line| match line.strip_prefix(LOG_DATA_PREFIX) {
+                Some(data) => format!("Program data: {data}"),
+                None => line,
+            })
+            .collect()
+    }
+
+    /// Send `instructions` and decode the events the escrow program emitted
+    pub async fn process_events(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> Vec<EscrowEvent> {
+        let logs = self.process_logs(instructions, signers).await;
+        events::decode_logs(&self.program_id, &logs)
+    }
+
+    async fn transaction(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> Transaction {
+        let payer = self.context.payer.insecure_clone();
+        let mut all_signers = vec![&payer];
+        all_signers.extend_from_slice(signers);
+
+        let blockhash = self.context.banks_client.get_latest_blockhash().await.unwrap();
+        let mut transaction =
+            Transaction::new_signed_with_payer(instructions, Some(&payer.pubkey()), &all_signers, blockhash);
+        // Retrying a failed transaction needs a fresh blockhash to get a new signature
+        if self.sent.contains(&transaction.signatures[0]) {
+            let blockhash = self.context.banks_client.get_new_latest_blockhash(&blockhash).await.unwrap();
+            transaction.sign(&all_signers, blockhash);
+        }
+        self.sent.insert(transaction.signatures[0]);
+        transaction
+    }
+
+    pub async fn balance(&mut self, key: &Pubkey) -> u64 {
+        self.context.banks_client.get_balance(*key).await.unwrap()
+    }
+
+    pub async fn account_exists(&mut self, key: &Pubkey) -> bool {
+        self.context.banks_client.get_account(*key).await.unwrap().is_some()
+    }
+
+    pub async fn escrow(&mut self, keys: &EscrowKeys) -> EscrowAccount {
+        let account = self.context.banks_client.get_account(keys.escrow).await.unwrap().unwrap();
+        EscrowAccount::from_account_data(&account.data).unwrap()
+    }
+
+    pub async fn escrow_state(&mut self, keys: &EscrowKeys) -> EscrowState {
+        self.escrow(keys).await.get_state().unwrap()
+    }
+
+    /// Simulate GetEscrowInfo and decode its return data
+    pub async fn escrow_info(&mut self, keys: &EscrowKeys) -> EscrowInfo {
+        let ix = client::get_escrow_info_ix(&self.program_id, keys);
+        let transaction = self.transaction(&[ix], &[]).await;
+        let simulation = self.context.banks_client.simulate_transaction(transaction).await.unwrap();
+        simulation.result.unwrap().unwrap();
+        let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+        client::decode_escrow_info(&self.program_id, &return_data.program_id, &return_data.data).unwrap()
+    }
+
+    /// Move the bank clock `seconds` forward, past deadlines set in offers
+    pub async fn advance_clock(&mut self, seconds: i64) {
+        let mut clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp += seconds;
+        self.context.set_sysvar(&clock);
+    }
+
+    pub async fn rent(&mut self, len: usize) -> u64 {
+        self.context.banks_client.get_rent().await.unwrap().minimum_balance(len)
+    }
+
+    /// Buyer creates an offer in `mint`, seller joins it
+    pub async fn open_escrow(&mut self, mint: Pubkey, token_program: Pubkey, amount: u64) -> EscrowKeys {
+        let keys = self.create_offer(mint, token_program, amount).await;
+        let seller = self.seller.insecure_clone();
+        let ix = client::join_offer_ix(&self.program_id, &keys.escrow, &seller.pubkey(), 1, false, None);
+        self.process(&[ix], &[&seller]).await.unwrap();
+        EscrowKeys { seller: seller.pubkey(), ..keys }
+    }
+
+    /// Like `open_escrow`, with the seller owing a bond of `seller_bond`
+    pub async fn open_bonded_escrow(
+        &mut self,
+        mint: Pubkey,
+        token_program: Pubkey,
+        amount: u64,
+        seller_bond: u64,
+    ) -> EscrowKeys {
+        let buyer = self.buyer.insecure_clone();
+        let seller = self.seller.insecure_clone();
+        let args = CreateOfferArgs { seller_bond, ..self.offer_args(mint, amount) };
+        let (ix, keys) = self.offer_ix(token_program, args);
+        self.process(&[ix], &[&buyer]).await.unwrap();
+        let ix = client::join_offer_ix(&self.program_id, &keys.escrow, &seller.pubkey(), 1, false, None);
+        self.process(&[ix], &[&seller]).await.unwrap();
+        EscrowKeys { seller: seller.pubkey(), ..keys }
+    }
+
+    /// Buyer creates an offer in `mint`
+    pub async fn create_offer(&mut self, mint: Pubkey, token_program: Pubkey, amount: u64) -> EscrowKeys {
+        let buyer = self.buyer.insecure_clone();
+        let (ix, keys) = self.create_offer_ix(mint, token_program, amount);
+        self.process(&[ix], &[&buyer]).await.unwrap();
+        keys
+    }
+
+    /// CreateOffer instruction of the buyer with a fresh seed, and the keys of the escrow it creates
+    pub fn create_offer_ix(&self, mint: Pubkey, token_program: Pubkey, amount: u64) -> (Instruction, EscrowKeys) {
+        self.offer_ix(token_program, self.offer_args(mint, amount))
+    }
+
+    /// CreateOffer instruction of the buyer with `args`, and the keys of the escrow it creates
+    pub fn offer_ix(&self, token_program: Pubkey, args: CreateOfferArgs) -> (Instruction, EscrowKeys) {
+        let (mint, with_seller_bond) = (args.mint, args.seller_bond > 0);
+        let (escrow, _) = pda::find_escrow_address(&self.program_id, &args.seed);
+        let ix = client::create_offer_ix(&self.program_id, &self.buyer.pubkey(), &self.fee_collector, args);
+
+        let keys = EscrowKeys {
+            escrow,
+            buyer: self.buyer.pubkey(),
+            seller: Pubkey::default(),
+            arbiter: self.arbiter.pubkey(),
+            mint,
+            token_program,
+            rent_payer: self.buyer.pubkey(),
+            settlement_fee_collector: None,
+            with_seller_bond,
+        };
+        (ix, keys)
+    }
+
+    /// Offer by the buyer with a fresh seed and the bank's arbiter, no deadlines or arbiter fee
+    pub fn offer_args(&self, mint: Pubkey, amount: u64) -> CreateOfferArgs {
+        CreateOfferArgs {
+            role: 0,
+            amount,
+            arbiter: self.arbiter.pubkey(),
+            mint,
+            seed: Pubkey::new_unique().to_bytes(),
+            funding_window: 0,
+            delivery_window: 0,
+            dispute_window: 0,
+            arbiter_opt_in: false,
+            arbiter_fee_mode: 0,
+            arbiter_fee: 0,
+            seller_bond: 0,
+        }
+    }
+
+    /// Create an SPL Token mint and fund an associated token account for every wallet
+    pub async fn create_mint(&mut self) -> Pubkey {
+        let payer = self.context.payer.insecure_clone();
+        let mint = Keypair::new();
+        let rent = self.rent(spl_token::state::Mint::LEN).await;
+        let instructions = [
+            system_instruction::create_account(
+                &payer.pubkey(),
+                &mint.pubkey(),
+                rent,
+                spl_token::state::Mint::LEN as u64,
+                &SPL_TOKEN_PROGRAM_ID,
+            ),
+            spl_token::instruction::initialize_mint2(&SPL_TOKEN_PROGRAM_ID, &mint.pubkey(), &payer.pubkey(), None, DECIMALS)
+                .unwrap(),
+        ];
+        self.process(&instructions, &[&mint]).await.unwrap();
+
+        let wallets = [
+            self.buyer.pubkey(),
+            self.seller.pubkey(),
+            self.arbiter.pubkey(),
+            self.outsider.pubkey(),
+        ];
+        for wallet in wallets {
+            let token_account = self.token_account(&wallet, &mint.pubkey());
+            let instructions = [
+                create_associated_token_account_ix(&payer.pubkey(), &wallet, &mint.pubkey()),
+                spl_token::instruction::mint_to(
+                    &SPL_TOKEN_PROGRAM_ID,
+                    &mint.pubkey(),
+                    &token_account,
+                    &payer.pubkey(),
+                    &[],
+                    10 * TOKEN_AMOUNT,
+                )
+                .unwrap(),
+            ];
+            self.process(&instructions, &[]).await.unwrap();
+        }
+        mint.pubkey()
+    }
+
+    /// Create the empty associated token account of `wallet`, e.g. for the fee collector
+    pub async fn create_token_account(&mut self, wallet: &Pubkey, mint: &Pubkey) -> Pubkey {
+        let payer = self.context.payer.pubkey();
+        self.process(&[create_associated_token_account_ix(&payer, wallet, mint)], &[]).await.unwrap();
+        self.token_account(wallet, mint)
+    }
+
+    pub fn token_account(&self, wallet: &Pubkey, mint: &Pubkey) -> Pubkey {
+        AccountHelper::associated_token_address(wallet, mint, &SPL_TOKEN_PROGRAM_ID)
+    }
+
+    pub async fn token_balance(&mut self, token_account: &Pubkey) -> u64 {
+        let account = self.context.banks_client.get_account(*token_account).await.unwrap().unwrap();
+        spl_token::state::Account::unpack(&account.data).unwrap().amount
+    }
+}
+
+fn create_associated_token_account_ix(payer: &Pubkey, wallet: &Pubkey, mint: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: ASSOCIATED_TOKEN_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(AccountHelper::associated_token_address(wallet, mint, &SPL_TOKEN_PROGRAM_ID), false),
+            AccountMeta::new_readonly(*wallet, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(SPL_TOKEN_PROGRAM_ID, false),
+        ],
+        data: vec![1],
+    }
+}
+
+pub fn sol_mint() -> (Pubkey, Pubkey) {
+    (NATIVE_MINT, SPL_TOKEN_PROGRAM_ID)
+}
+
+/// Assert that the transaction failed in its first instruction with `expected`
+pub fn assert_escrow_error(result: Result<(), BanksClientError>, expected: EscrowError) {
+    assert_instruction_error(result, InstructionError::Custom(expected as u32));
+}
+
+pub fn assert_instruction_error(result: Result<(), BanksClientError>, expected: InstructionError) {
+    match result.expect_err("transaction should fail").unwrap() {
+        TransactionError::InstructionError(0, error) => assert_eq!(error, expected),
+        other => panic!("unexpected transaction error: {:?}", other),
+    }
+}
+
+/// Prefix of the `Program log:` lines [`LogDataStubs`] writes for `sol_log_data`
+const LOG_DATA_PREFIX: &str = "Program log: sol_log_data: ";
+
+struct NoStubs;
+impl SyscallStubs for NoStubs {}
+
+/// program-test runs the program natively and its syscall stubs print `sol_log_data`
+/// to stdout instead of the transaction logs. This wraps them to log the fields in
+/// place, so `process_logs` can turn them back into the `Program data:` lines a
+/// validator would record.
+struct LogDataStubs(Box<dyn SyscallStubs>);
+
+impl SyscallStubs for LogDataStubs {
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        let fields: Vec<String> = fields.iter().map(|field| STANDARD.encode(field)).collect();
+        let prefix = LOG_DATA_PREFIX.strip_prefix("Program log: ").unwrap();
+        self.0.sol_log(&format!("{prefix}{}", fields.join(" ")));
+    }
+
+    fn sol_log(&self, message: &str) {
+        self.0.sol_log(message)
+    }
+    fn sol_log_compute_units(&self) {
+        self.0.sol_log_compute_units()
+    }
+    fn sol_remaining_compute_units(&self) -> u64 {
+        self.0.sol_remaining_compute_units()
+    }
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        self.0.sol_invoke_signed(instruction, account_infos, signers_seeds)
+    }
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_clock_sysvar(var_addr)
+    }
+    fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_epoch_schedule_sysvar(var_addr)
+    }
+    fn sol_get_fees_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_fees_sysvar(var_addr)
+    }
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_rent_sysvar(var_addr)
+    }
+    fn sol_get_epoch_rewards_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_epoch_rewards_sysvar(var_addr)
+    }
+    fn sol_get_last_restart_slot(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_last_restart_slot(var_addr)
+    }
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        self.0.sol_get_return_data()
+    }
+    fn sol_set_return_data(&self, data: &[u8]) {
+        self.0.sol_set_return_data(data)
+    }
+    fn sol_get_processed_sibling_instruction(&self, index: usize) -> Option<Instruction> {
+        self.0.sol_get_processed_sibling_instruction(index)
+    }
+    fn sol_get_stack_height(&self) -> u64 {
+        self.0.sol_get_stack_height()
+    }
+}
@@ -0,0 +1,192 @@
+//! A mock marketplace program drives escrows through `cpi::EscrowCpi`, acting as the buyer
+//! with its `authority` PDA: a system-owned account signing via `invoke_signed`.
+
+mod common;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use common::{assert_escrow_error, system_account, TestEnv, AMOUNT, FLAT_FEE};
+use solana_program_test::{processor, tokio};
+use solana_sdk::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    native_token::LAMPORTS_PER_SOL,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    signature::Signer,
+};
+use solana_smart_contract::{
+    client::{self, EscrowKeys},
+    cpi::EscrowCpi,
+    instructions::{CreateOfferArgs, EscrowError},
+    pda,
+    state::EscrowState,
+    utils::NATIVE_MINT,
+};
+
+const MARKETPLACE_ID: Pubkey = Pubkey::new_from_array([7; 32]);
+const AUTHORITY_SEED: &[u8] = b"authority";
+const AUTHORITY_BALANCE: u64 = 10 * LAMPORTS_PER_SOL;
+
+#[derive(BorshSerialize, BorshDeserialize)]
+enum MarketplaceInstruction {
+    /// Authority opens an offer as the buyer, `payer` covers the service fee and rent
+    CreateOffer { payer: Pubkey, fee_collector: Pubkey, args: CreateOfferArgs },
+    Fund { escrow: Pubkey },
+    /// Release to the seller, then check the escrow completed
+    Release { escrow: Pubkey },
+    /// Withdraw the unjoined offer, refunding `rent_payer`
+    Cancel { escrow: Pubkey, rent_payer: Pubkey },
+}
+
+fn authority() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[AUTHORITY_SEED], &MARKETPLACE_ID)
+}
+
+/// Accounts: the escrow program, then every account of the escrow instruction
+fn process_marketplace(_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let (escrow_program, accounts) = accounts.split_first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    let (authority, bump) = authority();
+    let signer_seeds: &[&[u8]] = &[AUTHORITY_SEED, &[bump]];
+    let signers = [signer_seeds];
+    let cpi = EscrowCpi::new(escrow_program, accounts).with_signer(&signers);
+    let escrow_keys = |escrow: &Pubkey| {
+        let escrow = accounts.iter().find(|account| account.key == escrow).ok_or(ProgramError::NotEnoughAccountKeys)?;
+        cpi.escrow_keys(escrow, Pubkey::default())
+    };
+
+    match MarketplaceInstruction::try_from_slice(data)? {
+        MarketplaceInstruction::CreateOffer { payer, fee_collector, args } => {
+            cpi.create_offer(&authority, Some(&payer), &fee_collector, args)
+        }
+        MarketplaceInstruction::Fund { escrow } => cpi.fund_escrow(&escrow_keys(&escrow)?),
+        MarketplaceInstruction::Release { escrow } => {
+            let keys = escrow_keys(&escrow)?;
+            cpi.confirm_escrow(&keys)?;
+            let info = cpi.get_escrow_info(&keys)?;
+            if info.escrow.get_state()? != EscrowState::Completed || info.remaining_amount != 0 {
+                return Err(ProgramError::InvalidAccountData);
+            }
+            Ok(())
+        }
+        MarketplaceInstruction::Cancel { escrow, rent_payer } => cpi.cancel_offer(&escrow, &authority, &rent_payer),
+    }
+}
+
+/// Wrap `escrow_ix` in a marketplace instruction; the authority signs inside the program
+fn marketplace_ix(escrow_ix: Instruction, instruction: MarketplaceInstruction) -> Instruction {
+    let authority = authority().0;
+    let mut accounts = vec![AccountMeta::new_readonly(escrow_ix.program_id, false)];
+    accounts.extend(escrow_ix.accounts.into_iter().map(|meta| AccountMeta {
+        is_signer: meta.is_signer && meta.pubkey != authority,
+        ..meta
+    }));
+    Instruction {
+        program_id: MARKETPLACE_ID,
+        accounts,
+        data: borsh::to_vec(&instruction).unwrap(),
+    }
+}
+
+async fn start() -> TestEnv {
+    TestEnv::start_with(|program_test| {
+        program_test.add_program("marketplace", MARKETPLACE_ID, processor!(process_marketplace));
+        program_test.add_account(authority().0, system_account(AUTHORITY_BALANCE));
+    })
+    .await
+}
+
+/// Marketplace authority creates an offer as the buyer, the bank's buyer wallet pays for it
+async fn create_offer(env: &mut TestEnv) -> EscrowKeys {
+    let authority = authority().0;
+    let payer = env.buyer.insecure_clone();
+    let args = env.offer_args(NATIVE_MINT, AMOUNT);
+    let (escrow, _) = pda::find_escrow_address(&env.program_id, &args.seed);
+
+    let escrow_ix = client::create_offer_with_payer_ix(
+        &env.program_id,
+        &authority,
+        &payer.pubkey(),
+        &env.fee_collector,
+        args.clone(),
+    );
+    let instruction =
+        MarketplaceInstruction::CreateOffer { payer: payer.pubkey(), fee_collector: env.fee_collector, args };
+    env.process(&[marketplace_ix(escrow_ix, instruction)], &[&payer]).await.unwrap();
+
+    EscrowKeys {
+        escrow,
+        buyer: authority,
+        seller: Pubkey::default(),
+        arbiter: env.arbiter.pubkey(),
+        mint: NATIVE_MINT,
+        token_program: Pubkey::default(),
+        rent_payer: payer.pubkey(),
+        settlement_fee_collector: None,
+        with_seller_bond: false,
+    }
+}
+
+#[tokio::test]
+async fn test_marketplace_creates_funds_and_releases() {
+    let mut env = start().await;
+    let program_id = env.program_id;
+    let seller = env.seller.insecure_clone();
+    let payer_lamports = env.balance(&env.buyer.pubkey()).await;
+
+    let keys = create_offer(&mut env).await;
+    let escrow = env.escrow(&keys).await;
+    assert_eq!(escrow.buyer, keys.buyer);
+    assert_eq!(escrow.rent_payer, keys.rent_payer);
+    // The payer covered the service fee and rent, the authority only funds the deal
+    let rent = env.balance(&keys.escrow).await + env.balance(&keys.vault(&program_id)).await;
+    assert_eq!(env.balance(&keys.rent_payer).await, payer_lamports - FLAT_FEE - rent);
+    assert_eq!(env.balance(&keys.buyer).await, AUTHORITY_BALANCE);
+
+    let ix = client::join_offer_ix(&program_id, &keys.escrow, &seller.pubkey(), 1, false, None);
+    env.process(&[ix], &[&seller]).await.unwrap();
+    let keys = EscrowKeys { seller: seller.pubkey(), ..keys };
+
+    let escrow_ix = client::fund_escrow_ix(&program_id, &keys);
+    env.process(&[marketplace_ix(escrow_ix, MarketplaceInstruction::Fund { escrow: keys.escrow })], &[])
+        .await
+        .unwrap();
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Funded);
+    assert_eq!(env.balance(&keys.buyer).await, AUTHORITY_BALANCE - AMOUNT);
+
+    env.process(&[client::seller_confirm_ix(&program_id, &keys)], &[&seller]).await.unwrap();
+    let seller_lamports = env.balance(&keys.seller).await;
+    // Release also reads GetEscrowInfo, which needs the vault too
+    let escrow_ix = client::confirm_escrow_ix(&program_id, &keys);
+    let ix = marketplace_ix(escrow_ix, MarketplaceInstruction::Release { escrow: keys.escrow });
+    env.process(&[ix], &[]).await.unwrap();
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Completed);
+    assert_eq!(env.balance(&keys.seller).await, seller_lamports + AMOUNT);
+
+    // Rent goes back to the payer
+    env.process(&[client::close_escrow_ix(&program_id, &keys)], &[]).await.unwrap();
+    assert_eq!(env.balance(&keys.rent_payer).await, payer_lamports - FLAT_FEE);
+}
+
+#[tokio::test]
+async fn test_initiator_cancels_offer_paid_by_payer() {
+    let mut env = start().await;
+    let program_id = env.program_id;
+    let payer = env.buyer.insecure_clone();
+    let payer_lamports = env.balance(&payer.pubkey()).await;
+    let keys = create_offer(&mut env).await;
+
+    // Paying for the offer does not make the payer its initiator
+    let ix = client::cancel_offer_ix(&program_id, &keys.escrow, &payer.pubkey(), &payer.pubkey());
+    assert_escrow_error(env.process(&[ix], &[&payer]).await, EscrowError::NotInitiator);
+    let ix = client::init_milestones_ix(&program_id, &keys.escrow, &payer.pubkey(), vec![AMOUNT]);
+    assert_escrow_error(env.process(&[ix], &[&payer]).await, EscrowError::NotInitiator);
+
+    // The authority cancels and the payer gets everything but the collected fee back
+    let escrow_ix = client::cancel_offer_ix(&program_id, &keys.escrow, &keys.buyer, &payer.pubkey());
+    let instruction = MarketplaceInstruction::Cancel { escrow: keys.escrow, rent_payer: payer.pubkey() };
+    env.process(&[marketplace_ix(escrow_ix, instruction)], &[]).await.unwrap();
+    assert!(!env.account_exists(&keys.escrow).await);
+    assert_eq!(env.balance(&payer.pubkey()).await, payer_lamports - FLAT_FEE);
+    assert_eq!(env.balance(&keys.buyer).await, AUTHORITY_BALANCE);
+}
@@ -0,0 +1,500 @@
+//! Native SOL escrow lifecycle driven through `process_instruction` in a local bank.
+
+mod common;
+
+use common::{assert_escrow_error, assert_instruction_error, sol_mint, TestEnv, AMOUNT, FLAT_FEE};
+use solana_program_test::tokio;
+use solana_sdk::{instruction::InstructionError, signature::Signer};
+use solana_smart_contract::{
+    client::{self, EscrowKeys},
+    events::EscrowEvent,
+    instructions::{CreateOfferArgs, EscrowError},
+    state::{EscrowAccount, EscrowState, ESCROW_VERSION},
+};
+
+const BOND: u64 = AMOUNT / 4;
+
+/// Open, join and fund a SOL escrow
+async fn funded_escrow(env: &mut TestEnv) -> EscrowKeys {
+    let (mint, token_program) = sol_mint();
+    let keys = env.open_escrow(mint, token_program, AMOUNT).await;
+    let buyer = env.buyer.insecure_clone();
+    env.process(&[client::fund_escrow_ix(&env.program_id, &keys)], &[&buyer]).await.unwrap();
+    keys
+}
+
+/// Open a SOL escrow with a seller bond of `BOND`, deposit the bond and fund it
+async fn bonded_escrow(env: &mut TestEnv) -> EscrowKeys {
+    let (mint, token_program) = sol_mint();
+    let keys = env.open_bonded_escrow(mint, token_program, AMOUNT, BOND).await;
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+    env.process(&[client::fund_collateral_ix(&env.program_id, &keys)], &[&seller]).await.unwrap();
+    env.process(&[client::fund_escrow_ix(&env.program_id, &keys)], &[&buyer]).await.unwrap();
+    keys
+}
+
+#[tokio::test]
+async fn test_buyer_confirmation_path() {
+    let mut env = TestEnv::start().await;
+    let (mint, token_program) = sol_mint();
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+    let buyer_start = env.balance(&buyer.pubkey()).await;
+    let collector_start = env.balance(&env.fee_collector.clone()).await;
+
+    // Create: buyer pays the flat fee plus escrow and vault rent
+    let keys = env.create_offer(mint, token_program, AMOUNT).await;
+    let escrow_rent = env.rent(EscrowAccount::LEN).await;
+    let vault_rent = env.rent(0).await;
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Created);
+    assert_eq!(env.balance(&buyer.pubkey()).await, buyer_start - FLAT_FEE - escrow_rent - vault_rent);
+    assert_eq!(env.balance(&env.fee_collector.clone()).await, collector_start + FLAT_FEE);
+
+    // Join
+    let ix = client::join_offer_ix(&env.program_id, &keys.escrow, &seller.pubkey(), 1, false, None);
+    env.process(&[ix], &[&seller]).await.unwrap();
+    let keys = EscrowKeys { seller: seller.pubkey(), ..keys };
+    let escrow = env.escrow(&keys).await;
+    assert_eq!(escrow.get_state().unwrap(), EscrowState::Initialized);
+    assert_eq!(escrow.seller, seller.pubkey());
+
+    // Fund
+    let vault = keys.vault(&env.program_id);
+    env.process(&[client::fund_escrow_ix(&env.program_id, &keys)], &[&buyer]).await.unwrap();
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Funded);
+    assert_eq!(env.escrow(&keys).await.funded_amount, AMOUNT);
+    assert_eq!(env.balance(&vault).await, vault_rent + AMOUNT);
+    let buyer_funded = env.balance(&buyer.pubkey()).await;
+    assert_eq!(buyer_funded, buyer_start - FLAT_FEE - escrow_rent - vault_rent - AMOUNT);
+
+    // Seller confirms, buyer releases
+    let seller_start = env.balance(&seller.pubkey()).await;
+    env.process(&[client::seller_confirm_ix(&env.program_id, &keys)], &[&seller]).await.unwrap();
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::SellerConfirmed);
+
+    env.process(&[client::confirm_escrow_ix(&env.program_id, &keys)], &[&buyer]).await.unwrap();
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Completed);
+    assert_eq!(env.balance(&seller.pubkey()).await, seller_start + AMOUNT);
+    assert_eq!(env.balance(&vault).await, vault_rent);
+
+    // Close needs no signature; rent goes back to the buyer
+    env.process(&[client::close_escrow_ix(&env.program_id, &keys)], &[]).await.unwrap();
+    assert!(!env.account_exists(&keys.escrow).await);
+    assert!(!env.account_exists(&vault).await);
+    assert_eq!(env.balance(&buyer.pubkey()).await, buyer_funded + escrow_rent + vault_rent);
+}
+
+#[tokio::test]
+async fn test_arbiter_confirm_after_dispute() {
+    let mut env = TestEnv::start().await;
+    let keys = funded_escrow(&mut env).await;
+    let buyer = env.buyer.insecure_clone();
+    let arbiter = env.arbiter.insecure_clone();
+    let seller_start = env.balance(&keys.seller).await;
+
+    // No dispute and no opt-in: the arbiter may not act
+    let result = env.process(&[client::arbiter_confirm_ix(&env.program_id, &keys)], &[&arbiter]).await;
+    assert_escrow_error(result, EscrowError::ArbitrationNotAllowed);
+
+    env.process(&[client::raise_dispute_ix(&env.program_id, &keys.escrow, &buyer.pubkey())], &[&buyer])
+        .await
+        .unwrap();
+    let escrow = env.escrow(&keys).await;
+    assert_eq!(escrow.get_state().unwrap(), EscrowState::Disputed);
+    assert_eq!(escrow.dispute_raised_by, buyer.pubkey());
+
+    env.process(&[client::arbiter_confirm_ix(&env.program_id, &keys)], &[&arbiter]).await.unwrap();
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Completed);
+    assert_eq!(env.balance(&keys.seller).await, seller_start + AMOUNT);
+}
+
+#[tokio::test]
+async fn test_arbiter_cancel_after_dispute() {
+    let mut env = TestEnv::start().await;
+    let keys = funded_escrow(&mut env).await;
+    let seller = env.seller.insecure_clone();
+    let arbiter = env.arbiter.insecure_clone();
+    let buyer_funded = env.balance(&keys.buyer).await;
+
+    env.process(&[client::raise_dispute_ix(&env.program_id, &keys.escrow, &seller.pubkey())], &[&seller])
+        .await
+        .unwrap();
+    env.process(&[client::arbiter_cancel_ix(&env.program_id, &keys)], &[&arbiter]).await.unwrap();
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Cancelled);
+    assert_eq!(env.balance(&keys.buyer).await, buyer_funded + AMOUNT);
+
+    // Settled escrows cannot be settled again
+    let result = env.process(&[client::arbiter_confirm_ix(&env.program_id, &keys)], &[&arbiter]).await;
+    assert_escrow_error(result, EscrowError::ArbitrationNotAllowed);
+}
+
+#[tokio::test]
+async fn test_mutual_cancel_and_close() {
+    let mut env = TestEnv::start().await;
+    let keys = funded_escrow(&mut env).await;
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+    let escrow_rent = env.rent(EscrowAccount::LEN).await;
+    let vault_rent = env.rent(0).await;
+    let buyer_funded = env.balance(&keys.buyer).await;
+
+    // Both signatures are required
+    let mut ix = client::mutual_cancel_ix(&env.program_id, &keys);
+    ix.accounts[1].is_signer = false;
+    assert_instruction_error(env.process(&[ix], &[&buyer]).await, InstructionError::MissingRequiredSignature);
+
+    // Only terminal escrows can be closed
+    let outsider = env.outsider.insecure_clone();
+    let result = env.process(&[client::close_escrow_ix(&env.program_id, &keys)], &[]).await;
+    assert_escrow_error(result, EscrowError::InvalidState);
+
+    env.process(&[client::mutual_cancel_ix(&env.program_id, &keys)], &[&buyer, &seller]).await.unwrap();
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Cancelled);
+    assert_eq!(env.balance(&keys.buyer).await, buyer_funded + AMOUNT);
+
+    // Rent cannot be redirected to someone else
+    let hijacked = EscrowKeys { rent_payer: outsider.pubkey(), ..keys.clone() };
+    let result = env.process(&[client::close_escrow_ix(&env.program_id, &hijacked)], &[]).await;
+    assert_escrow_error(result, EscrowError::NotRentPayer);
+
+    env.process(&[client::close_escrow_ix(&env.program_id, &keys)], &[]).await.unwrap();
+    assert!(!env.account_exists(&keys.escrow).await);
+    assert_eq!(env.balance(&keys.buyer).await, buyer_funded + AMOUNT + escrow_rent + vault_rent);
+}
+
+#[tokio::test]
+async fn test_close_requires_vault_and_milestones() {
+    let mut env = TestEnv::start().await;
+    let (mint, token_program) = sol_mint();
+    let program_id = env.program_id;
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+    let keys = env.create_offer(mint, token_program, AMOUNT).await;
+    let ix = client::init_milestones_ix(&program_id, &keys.escrow, &buyer.pubkey(), vec![AMOUNT / 2, AMOUNT / 2]);
+    env.process(&[ix], &[&buyer]).await.unwrap();
+    let ix = client::join_offer_ix(&program_id, &keys.escrow, &seller.pubkey(), 1, false, None);
+    env.process(&[ix], &[&seller]).await.unwrap();
+    let keys = EscrowKeys { seller: seller.pubkey(), ..keys };
+    env.process(&[client::fund_escrow_ix(&program_id, &keys)], &[&buyer]).await.unwrap();
+    env.process(&[client::mutual_cancel_ix(&program_id, &keys)], &[&buyer, &seller]).await.unwrap();
+    let vault = keys.vault(&program_id);
+    let milestones = keys.milestones(&program_id);
+
+    // Leaving out the milestone account, or the vault as well, would strand their rent
+    let mut ix = client::close_escrow_ix(&program_id, &keys);
+    ix.accounts.truncate(3);
+    assert_instruction_error(env.process(&[ix.clone()], &[]).await, InstructionError::NotEnoughAccountKeys);
+    ix.accounts.truncate(2);
+    assert_instruction_error(env.process(&[ix], &[]).await, InstructionError::NotEnoughAccountKeys);
+
+    // Nor may another account stand in for either
+    let mut ix = client::close_escrow_ix(&program_id, &keys);
+    ix.accounts[2].pubkey = env.outsider.pubkey();
+    assert_escrow_error(env.process(&[ix], &[]).await, EscrowError::InvalidVault);
+    let mut ix = client::close_escrow_ix(&program_id, &keys);
+    ix.accounts[3].pubkey = vault;
+    assert_escrow_error(env.process(&[ix], &[]).await, EscrowError::InvalidAccountDiscriminator);
+    assert!(env.account_exists(&keys.escrow).await);
+
+    env.process(&[client::close_escrow_ix(&program_id, &keys)], &[]).await.unwrap();
+    assert!(!env.account_exists(&keys.escrow).await);
+    assert!(!env.account_exists(&vault).await);
+    assert!(!env.account_exists(&milestones).await);
+}
+
+#[tokio::test]
+async fn test_expired_refund_waits_for_confirmed_milestones() {
+    let mut env = TestEnv::start().await;
+    let (mint, token_program) = sol_mint();
+    let program_id = env.program_id;
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+    let args = CreateOfferArgs { delivery_window: 3_600, ..env.offer_args(mint, AMOUNT) };
+    let (ix, keys) = env.offer_ix(token_program, args);
+    env.process(&[ix], &[&buyer]).await.unwrap();
+    let ix = client::init_milestones_ix(&program_id, &keys.escrow, &buyer.pubkey(), vec![AMOUNT / 2, AMOUNT / 2]);
+    env.process(&[ix], &[&buyer]).await.unwrap();
+    let ix = client::join_offer_ix(&program_id, &keys.escrow, &seller.pubkey(), 1, false, None);
+    env.process(&[ix], &[&seller]).await.unwrap();
+    let keys = EscrowKeys { seller: seller.pubkey(), ..keys };
+    env.process(&[client::fund_escrow_ix(&program_id, &keys)], &[&buyer]).await.unwrap();
+
+    // The seller delivers the first milestone, the buyer goes silent past the deadline
+    env.process(&[client::confirm_milestone_ix(&program_id, &keys, 0)], &[&seller]).await.unwrap();
+    env.advance_clock(3_601).await;
+    let result = env.process(&[client::claim_expired_refund_ix(&program_id, &keys)], &[]).await;
+    assert_escrow_error(result, EscrowError::MilestonesAwaitingRelease);
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Funded);
+
+    // Once the delivered milestone is paid, only the undelivered one is refunded
+    let seller_start = env.balance(&keys.seller).await;
+    env.process(&[client::release_milestone_ix(&program_id, &keys, 0)], &[&buyer]).await.unwrap();
+    assert_eq!(env.balance(&keys.seller).await, seller_start + AMOUNT / 2);
+    let buyer_start = env.balance(&keys.buyer).await;
+    env.process(&[client::claim_expired_refund_ix(&program_id, &keys)], &[]).await.unwrap();
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Cancelled);
+    assert_eq!(env.balance(&keys.buyer).await, buyer_start + AMOUNT / 2);
+}
+
+#[tokio::test]
+async fn test_unauthorized_signers_rejected() {
+    let mut env = TestEnv::start().await;
+    let (mint, token_program) = sol_mint();
+    let keys = env.open_escrow(mint, token_program, AMOUNT).await;
+    let program_id = env.program_id;
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+    let arbiter = env.arbiter.insecure_clone();
+    let outsider = env.outsider.insecure_clone();
+
+    // Funding: only the buyer
+    for impostor in [&seller, &arbiter, &outsider] {
+        let forged = EscrowKeys { buyer: impostor.pubkey(), ..keys.clone() };
+        let result = env.process(&[client::fund_escrow_ix(&program_id, &forged)], &[impostor]).await;
+        assert_escrow_error(result, EscrowError::NotBuyer);
+    }
+    env.process(&[client::fund_escrow_ix(&program_id, &keys)], &[&buyer]).await.unwrap();
+
+    // Seller confirmation: only the seller
+    for impostor in [&buyer, &arbiter, &outsider] {
+        let forged = EscrowKeys { seller: impostor.pubkey(), ..keys.clone() };
+        let result = env.process(&[client::seller_confirm_ix(&program_id, &forged)], &[impostor]).await;
+        assert_escrow_error(result, EscrowError::NotSeller);
+    }
+
+    // Disputes: only buyer or seller
+    for impostor in [&arbiter, &outsider] {
+        let result = env
+            .process(&[client::raise_dispute_ix(&program_id, &keys.escrow, &impostor.pubkey())], &[impostor])
+            .await;
+        assert_escrow_error(result, EscrowError::NotBuyerOrSeller);
+    }
+
+    // Arbiter rulings: only the arbiter
+    env.process(&[client::raise_dispute_ix(&program_id, &keys.escrow, &buyer.pubkey())], &[&buyer])
+        .await
+        .unwrap();
+    for impostor in [&buyer, &seller, &outsider] {
+        let forged = EscrowKeys { arbiter: impostor.pubkey(), ..keys.clone() };
+        let result = env.process(&[client::arbiter_confirm_ix(&program_id, &forged)], &[impostor]).await;
+        assert_escrow_error(result, EscrowError::NotArbiter);
+        let result = env.process(&[client::arbiter_cancel_ix(&program_id, &forged)], &[impostor]).await;
+        assert_escrow_error(result, EscrowError::NotArbiter);
+    }
+
+    // Payouts cannot be redirected
+    let redirected = EscrowKeys { seller: outsider.pubkey(), ..keys.clone() };
+    let result = env.process(&[client::arbiter_confirm_ix(&program_id, &redirected)], &[&arbiter]).await;
+    assert_escrow_error(result, EscrowError::NotSeller);
+    let redirected = EscrowKeys { buyer: outsider.pubkey(), ..keys.clone() };
+    let result = env.process(&[client::arbiter_cancel_ix(&program_id, &redirected)], &[&arbiter]).await;
+    assert_escrow_error(result, EscrowError::NotBuyer);
+
+    // Mutual cancel: both real parties
+    let forged = EscrowKeys { seller: outsider.pubkey(), ..keys.clone() };
+    let result = env.process(&[client::mutual_cancel_ix(&program_id, &forged)], &[&buyer, &outsider]).await;
+    assert_escrow_error(result, EscrowError::NotSeller);
+
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Disputed);
+    assert_eq!(env.balance(&keys.vault(&program_id)).await, env.rent(0).await + AMOUNT);
+}
+
+#[tokio::test]
+async fn test_invalid_role_rejected() {
+    let mut env = TestEnv::start().await;
+    let (mint, token_program) = sol_mint();
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+
+    let (ix, _) = env.offer_ix(token_program, CreateOfferArgs { role: 2, ..env.offer_args(mint, AMOUNT) });
+    let result = env.process(&[ix], &[&buyer]).await;
+    assert_escrow_error(result, EscrowError::InvalidRole);
+
+    let keys = env.create_offer(mint, token_program, AMOUNT).await;
+    let ix = client::join_offer_ix(&env.program_id, &keys.escrow, &seller.pubkey(), 2, false, None);
+    let result = env.process(&[ix], &[&seller]).await;
+    assert_escrow_error(result, EscrowError::InvalidRole);
+    assert_eq!(env.escrow(&keys).await.seller, Default::default());
+}
+
+#[tokio::test]
+async fn test_buyer_release_requires_seller_confirmation() {
+    let mut env = TestEnv::start().await;
+    let keys = funded_escrow(&mut env).await;
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+
+    let result = env.process(&[client::confirm_escrow_ix(&env.program_id, &keys)], &[&buyer]).await;
+    assert_escrow_error(result, EscrowError::InvalidState);
+
+    env.process(&[client::seller_confirm_ix(&env.program_id, &keys)], &[&seller]).await.unwrap();
+    let forged = EscrowKeys { buyer: seller.pubkey(), ..keys.clone() };
+    let result = env.process(&[client::confirm_escrow_ix(&env.program_id, &forged)], &[&seller]).await;
+    assert_escrow_error(result, EscrowError::NotBuyer);
+}
+
+#[tokio::test]
+async fn test_lifecycle_events() {
+    let mut env = TestEnv::start().await;
+    let (mint, token_program) = sol_mint();
+    let program_id = env.program_id;
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+    let arbiter = env.arbiter.insecure_clone();
+
+    let (ix, keys) = env.create_offer_ix(mint, token_program, AMOUNT);
+    let events = env.process_events(&[ix], &[&buyer]).await;
+    assert_eq!(
+        events,
+        [EscrowEvent::OfferCreated {
+            escrow: keys.escrow,
+            initiator: buyer.pubkey(),
+            role: 0,
+            amount: AMOUNT,
+            mint,
+            arbiter: arbiter.pubkey(),
+        }]
+    );
+
+    let ix = client::join_offer_ix(&program_id, &keys.escrow, &seller.pubkey(), 1, false, None);
+    let events = env.process_events(&[ix], &[&seller]).await;
+    assert_eq!(events, [EscrowEvent::Joined { escrow: keys.escrow, joiner: seller.pubkey(), role: 1 }]);
+    let keys = EscrowKeys { seller: seller.pubkey(), ..keys };
+
+    let events = env.process_events(&[client::fund_escrow_ix(&program_id, &keys)], &[&buyer]).await;
+    assert_eq!(events, [EscrowEvent::Funded { escrow: keys.escrow, buyer: buyer.pubkey(), amount: AMOUNT }]);
+
+    let ix = client::raise_dispute_ix(&program_id, &keys.escrow, &seller.pubkey());
+    let events = env.process_events(&[ix], &[&seller]).await;
+    assert_eq!(events, [EscrowEvent::Disputed { escrow: keys.escrow, raised_by: seller.pubkey() }]);
+
+    // 25% to the seller, the rest back to the buyer
+    let ix = client::arbiter_settle_ix(&program_id, &keys, 0, 2_500);
+    let events = env.process_events(&[ix], &[&arbiter]).await;
+    assert_eq!(
+        events,
+        [EscrowEvent::Settled {
+            escrow: keys.escrow,
+            seller_amount: AMOUNT / 4,
+            buyer_amount: AMOUNT - AMOUNT / 4,
+            fee: 0,
+            arbiter_fee: 0,
+        }]
+    );
+
+    let lamports = env.balance(&keys.escrow).await + env.balance(&keys.vault(&program_id)).await;
+    let events = env.process_events(&[client::close_escrow_ix(&program_id, &keys)], &[]).await;
+    assert_eq!(events, [EscrowEvent::Closed { escrow: keys.escrow, rent_payer: buyer.pubkey(), lamports }]);
+}
+
+#[tokio::test]
+async fn test_escrow_info_return_data() {
+    let mut env = TestEnv::start().await;
+    let (mint, token_program) = sol_mint();
+    let keys = env.open_escrow(mint, token_program, AMOUNT).await;
+    let buyer = env.buyer.insecure_clone();
+    let vault = keys.vault(&env.program_id);
+
+    let info = env.escrow_info(&keys).await;
+    assert_eq!(info.escrow, env.escrow(&keys).await);
+    assert_eq!(info.version, ESCROW_VERSION);
+    assert_eq!(info.vault_balance, Some(env.balance(&vault).await));
+    assert_eq!(info.remaining_amount, 0);
+    assert!(!info.can_be_closed);
+
+    env.process(&[client::fund_escrow_ix(&env.program_id, &keys)], &[&buyer]).await.unwrap();
+    let vault_lamports = env.balance(&vault).await;
+    let info = env.escrow_info(&keys).await;
+    assert_eq!(info.escrow.get_state().unwrap(), EscrowState::Funded);
+    assert_eq!(info.vault_balance, Some(vault_lamports));
+    assert_eq!(info.remaining_amount, AMOUNT);
+    assert_eq!(info.auto_release_after, None);
+    assert!(!info.arbiter_may_act);
+}
+
+#[tokio::test]
+async fn test_seller_bond_returned_on_completion() {
+    let mut env = TestEnv::start().await;
+    let (mint, token_program) = sol_mint();
+    let keys = env.open_bonded_escrow(mint, token_program, AMOUNT, BOND).await;
+    let program_id = env.program_id;
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+    let vault = keys.vault(&program_id);
+    let vault_rent = env.rent(0).await;
+    assert!(env.escrow_info(&keys).await.collateral_due);
+
+    // The buyer may not fund before the bond is in, and the bond is deposited once
+    let result = env.process(&[client::fund_escrow_ix(&program_id, &keys)], &[&buyer]).await;
+    assert_escrow_error(result, EscrowError::CollateralNotFunded);
+
+    let seller_start = env.balance(&seller.pubkey()).await;
+    let events = env.process_events(&[client::fund_collateral_ix(&program_id, &keys)], &[&seller]).await;
+    assert_eq!(events, [EscrowEvent::CollateralFunded { escrow: keys.escrow, seller: seller.pubkey(), amount: BOND }]);
+    let escrow = env.escrow(&keys).await;
+    assert_eq!(escrow.get_state().unwrap(), EscrowState::Initialized);
+    assert_eq!(escrow.funded_bond, BOND);
+    assert_eq!(env.balance(&seller.pubkey()).await, seller_start - BOND);
+    let result = env.process(&[client::fund_collateral_ix(&program_id, &keys)], &[&seller]).await;
+    assert_escrow_error(result, EscrowError::NoCollateralDue);
+
+    env.process(&[client::fund_escrow_ix(&program_id, &keys)], &[&buyer]).await.unwrap();
+    assert_eq!(env.balance(&vault).await, vault_rent + AMOUNT + BOND);
+    let info = env.escrow_info(&keys).await;
+    assert!(!info.collateral_due);
+    assert_eq!(info.held_collateral, BOND);
+
+    // Release pays the amount and returns the bond
+    env.process(&[client::seller_confirm_ix(&program_id, &keys)], &[&seller]).await.unwrap();
+    let events = env.process_events(&[client::confirm_escrow_ix(&program_id, &keys)], &[&buyer]).await;
+    assert!(events.contains(&EscrowEvent::CollateralReleased { escrow: keys.escrow, seller_amount: BOND, buyer_amount: 0 }));
+    assert_eq!(env.balance(&seller.pubkey()).await, seller_start + AMOUNT);
+    assert_eq!(env.balance(&vault).await, vault_rent);
+    assert_eq!(env.escrow_info(&keys).await.held_collateral, 0);
+}
+
+#[tokio::test]
+async fn test_seller_bond_forfeited_on_arbiter_cancel() {
+    let mut env = TestEnv::start().await;
+    let keys = bonded_escrow(&mut env).await;
+    let buyer = env.buyer.insecure_clone();
+    let arbiter = env.arbiter.insecure_clone();
+    let buyer_funded = env.balance(&keys.buyer).await;
+    let seller_start = env.balance(&keys.seller).await;
+
+    env.process(&[client::raise_dispute_ix(&env.program_id, &keys.escrow, &buyer.pubkey())], &[&buyer])
+        .await
+        .unwrap();
+    env.process(&[client::arbiter_cancel_ix(&env.program_id, &keys)], &[&arbiter]).await.unwrap();
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Cancelled);
+    assert_eq!(env.balance(&keys.buyer).await, buyer_funded + AMOUNT + BOND);
+    assert_eq!(env.balance(&keys.seller).await, seller_start);
+}
+
+#[tokio::test]
+async fn test_seller_bond_split_by_arbiter_and_returned_on_mutual_cancel() {
+    let mut env = TestEnv::start().await;
+    let keys = bonded_escrow(&mut env).await;
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+    let arbiter = env.arbiter.insecure_clone();
+    let buyer_funded = env.balance(&keys.buyer).await;
+    let seller_start = env.balance(&keys.seller).await;
+
+    // A 25/75 split of the vault splits the bond the same way
+    env.process(&[client::raise_dispute_ix(&env.program_id, &keys.escrow, &seller.pubkey())], &[&seller])
+        .await
+        .unwrap();
+    env.process(&[client::arbiter_settle_ix(&env.program_id, &keys, 0, 2_500)], &[&arbiter]).await.unwrap();
+    assert_eq!(env.balance(&keys.seller).await, seller_start + (AMOUNT + BOND) / 4);
+    assert_eq!(env.balance(&keys.buyer).await, buyer_funded + (AMOUNT + BOND) * 3 / 4);
+
+    // Calling off the deal together is not the seller's fault
+    let keys = bonded_escrow(&mut env).await;
+    let buyer_funded = env.balance(&keys.buyer).await;
+    let seller_start = env.balance(&keys.seller).await;
+    env.process(&[client::mutual_cancel_ix(&env.program_id, &keys)], &[&buyer, &seller]).await.unwrap();
+    assert_eq!(env.balance(&keys.buyer).await, buyer_funded + AMOUNT);
+    assert_eq!(env.balance(&keys.seller).await, seller_start + BOND);
+}
@@ -0,0 +1,239 @@
+//! SPL Token escrow lifecycle driven through `process_instruction` in a local bank.
+
+mod common;
+
+use common::{assert_escrow_error, assert_instruction_error, TestEnv, FLAT_FEE, TOKEN_AMOUNT};
+use solana_program_test::tokio;
+use solana_sdk::{instruction::InstructionError, pubkey::Pubkey, signature::Signer};
+use solana_smart_contract::{
+    client::{self, EscrowKeys},
+    instructions::{ConfigArgs, EscrowError},
+    state::EscrowState,
+    utils::{SPL_TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID},
+};
+
+/// Open, join and fund a token escrow
+async fn funded_escrow(env: &mut TestEnv) -> EscrowKeys {
+    let mint = env.create_mint().await;
+    let keys = env.open_escrow(mint, SPL_TOKEN_PROGRAM_ID, TOKEN_AMOUNT).await;
+    let buyer = env.buyer.insecure_clone();
+    env.process(&[client::fund_escrow_ix(&env.program_id, &keys)], &[&buyer]).await.unwrap();
+    keys
+}
+
+#[tokio::test]
+async fn test_buyer_confirmation_path() {
+    let mut env = TestEnv::start().await;
+    let mint = env.create_mint().await;
+    let keys = env.open_escrow(mint, SPL_TOKEN_PROGRAM_ID, TOKEN_AMOUNT).await;
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+    let vault_token = keys.token_account(&keys.vault(&env.program_id));
+    let buyer_token = keys.token_account(&keys.buyer);
+    let seller_token = keys.token_account(&keys.seller);
+    let buyer_tokens = env.token_balance(&buyer_token).await;
+    let seller_tokens = env.token_balance(&seller_token).await;
+
+    // The vault token account is created on funding
+    assert!(!env.account_exists(&vault_token).await);
+    env.process(&[client::fund_escrow_ix(&env.program_id, &keys)], &[&buyer]).await.unwrap();
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Funded);
+    assert_eq!(env.token_balance(&vault_token).await, TOKEN_AMOUNT);
+    assert_eq!(env.token_balance(&buyer_token).await, buyer_tokens - TOKEN_AMOUNT);
+
+    env.process(&[client::seller_confirm_ix(&env.program_id, &keys)], &[&seller]).await.unwrap();
+    env.process(&[client::confirm_escrow_ix(&env.program_id, &keys)], &[&buyer]).await.unwrap();
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Completed);
+    assert_eq!(env.token_balance(&vault_token).await, 0);
+    assert_eq!(env.token_balance(&seller_token).await, seller_tokens + TOKEN_AMOUNT);
+
+    // Close also closes the vault token account, returning all rent to the buyer
+    let buyer_lamports = env.balance(&keys.buyer).await;
+    let reclaimed = env.balance(&keys.escrow).await
+        + env.balance(&keys.vault(&env.program_id)).await
+        + env.balance(&vault_token).await;
+    env.process(&[client::close_escrow_ix(&env.program_id, &keys)], &[]).await.unwrap();
+    assert!(!env.account_exists(&keys.escrow).await);
+    assert!(!env.account_exists(&vault_token).await);
+    assert_eq!(env.balance(&keys.buyer).await, buyer_lamports + reclaimed);
+}
+
+#[tokio::test]
+async fn test_arbiter_rulings() {
+    let mut env = TestEnv::start().await;
+    let keys = funded_escrow(&mut env).await;
+    let seller = env.seller.insecure_clone();
+    let arbiter = env.arbiter.insecure_clone();
+    let buyer_token = keys.token_account(&keys.buyer);
+    let buyer_tokens = env.token_balance(&buyer_token).await;
+
+    let result = env.process(&[client::arbiter_cancel_ix(&env.program_id, &keys)], &[&arbiter]).await;
+    assert_escrow_error(result, EscrowError::ArbitrationNotAllowed);
+
+    env.process(&[client::raise_dispute_ix(&env.program_id, &keys.escrow, &seller.pubkey())], &[&seller])
+        .await
+        .unwrap();
+    let forged = EscrowKeys { arbiter: seller.pubkey(), ..keys.clone() };
+    let result = env.process(&[client::arbiter_cancel_ix(&env.program_id, &forged)], &[&seller]).await;
+    assert_escrow_error(result, EscrowError::NotArbiter);
+
+    env.process(&[client::arbiter_cancel_ix(&env.program_id, &keys)], &[&arbiter]).await.unwrap();
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Cancelled);
+    assert_eq!(env.token_balance(&buyer_token).await, buyer_tokens + TOKEN_AMOUNT);
+}
+
+#[tokio::test]
+async fn test_mutual_cancel_refunds_tokens() {
+    let mut env = TestEnv::start().await;
+    let keys = funded_escrow(&mut env).await;
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+    let buyer_token = keys.token_account(&keys.buyer);
+    let buyer_tokens = env.token_balance(&buyer_token).await;
+
+    env.process(&[client::mutual_cancel_ix(&env.program_id, &keys)], &[&buyer, &seller]).await.unwrap();
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::Cancelled);
+    assert_eq!(env.token_balance(&buyer_token).await, buyer_tokens + TOKEN_AMOUNT);
+
+    env.process(&[client::close_escrow_ix(&env.program_id, &keys)], &[]).await.unwrap();
+    assert!(!env.account_exists(&keys.token_account(&keys.vault(&env.program_id))).await);
+}
+
+#[tokio::test]
+async fn test_upfront_percentage_fee_taken_in_tokens() {
+    let mut env = TestEnv::start().await;
+    let admin = env.admin.insecure_clone();
+    let config = ConfigArgs {
+        fee_collector: env.fee_collector,
+        flat_fee: FLAT_FEE,
+        fee_bps: 200,
+        fee_on_settlement: 0,
+        cancel_refund_bps: 0,
+    };
+    let ix = client::update_config_ix(&env.program_id, &admin.pubkey(), &admin.pubkey(), config);
+    env.process(&[ix], &[&admin]).await.unwrap();
+    let mint = env.create_mint().await;
+    let collector_token = env.create_token_account(&env.fee_collector.clone(), &mint).await;
+    let collector_lamports = env.balance(&env.fee_collector.clone()).await;
+
+    // Only the flat fee is paid in lamports, the percentage is locked in for settlement
+    let keys = env.open_escrow(mint, SPL_TOKEN_PROGRAM_ID, TOKEN_AMOUNT).await;
+    assert_eq!(env.balance(&env.fee_collector.clone()).await, collector_lamports + FLAT_FEE);
+    let escrow = env.escrow(&keys).await;
+    assert_eq!(escrow.settlement_fee_bps, 200);
+
+    let keys = EscrowKeys::from_escrow(keys.escrow, &escrow, SPL_TOKEN_PROGRAM_ID);
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+    let seller_token = keys.token_account(&keys.seller);
+    let seller_tokens = env.token_balance(&seller_token).await;
+    env.process(&[client::fund_escrow_ix(&env.program_id, &keys)], &[&buyer]).await.unwrap();
+    env.process(&[client::seller_confirm_ix(&env.program_id, &keys)], &[&seller]).await.unwrap();
+    env.process(&[client::confirm_escrow_ix(&env.program_id, &keys)], &[&buyer]).await.unwrap();
+    assert_eq!(env.token_balance(&collector_token).await, TOKEN_AMOUNT / 50);
+    assert_eq!(env.token_balance(&seller_token).await, seller_tokens + TOKEN_AMOUNT - TOKEN_AMOUNT / 50);
+}
+
+#[tokio::test]
+async fn test_close_requires_the_vault_token_account() {
+    let mut env = TestEnv::start().await;
+    let keys = funded_escrow(&mut env).await;
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+    let vault_token = keys.token_account(&keys.vault(&env.program_id));
+    env.process(&[client::mutual_cancel_ix(&env.program_id, &keys)], &[&buyer, &seller]).await.unwrap();
+
+    // An empty account in place of the vault ATA must not skip closing it
+    let mut ix = client::close_escrow_ix(&env.program_id, &keys);
+    let meta = ix.accounts.iter_mut().find(|meta| meta.pubkey == vault_token).unwrap();
+    meta.pubkey = Pubkey::new_unique();
+    let result = env.process(&[ix], &[]).await;
+    assert_escrow_error(result, EscrowError::InvalidVaultTokenAccount);
+
+    // Nor may the ATA under the other token program, which does not exist
+    let other_program = EscrowKeys { token_program: TOKEN_2022_PROGRAM_ID, ..keys };
+    let result = env.process(&[client::close_escrow_ix(&env.program_id, &other_program)], &[]).await;
+    assert_escrow_error(result, EscrowError::InvalidVaultTokenAccount);
+    assert!(env.account_exists(&keys.escrow).await);
+
+    env.process(&[client::close_escrow_ix(&env.program_id, &keys)], &[]).await.unwrap();
+    assert!(!env.account_exists(&vault_token).await);
+}
+
+#[tokio::test]
+async fn test_payout_to_foreign_token_account_rejected() {
+    let mut env = TestEnv::start().await;
+    let keys = funded_escrow(&mut env).await;
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+    let outsider_token = keys.token_account(&env.outsider.pubkey());
+    env.process(&[client::seller_confirm_ix(&env.program_id, &keys)], &[&seller]).await.unwrap();
+
+    // Seller wallet is correct but the destination token account belongs to someone else
+    let mut ix = client::confirm_escrow_ix(&env.program_id, &keys);
+    let seller_token = keys.token_account(&keys.seller);
+    let meta = ix.accounts.iter_mut().find(|meta| meta.pubkey == seller_token).unwrap();
+    meta.pubkey = outsider_token;
+    let result = env.process(&[ix], &[&buyer]).await;
+    assert_escrow_error(result, EscrowError::TokenAccountOwnerMismatch);
+    assert_eq!(env.escrow_state(&keys).await, EscrowState::SellerConfirmed);
+}
+
+#[tokio::test]
+async fn test_escrow_info_reports_vault_tokens() {
+    let mut env = TestEnv::start().await;
+    let mint = env.create_mint().await;
+    let keys = env.open_escrow(mint, SPL_TOKEN_PROGRAM_ID, TOKEN_AMOUNT).await;
+    let buyer = env.buyer.insecure_clone();
+
+    // No vault token account before funding
+    assert_eq!(env.escrow_info(&keys).await.vault_balance, Some(0));
+
+    env.process(&[client::fund_escrow_ix(&env.program_id, &keys)], &[&buyer]).await.unwrap();
+    let info = env.escrow_info(&keys).await;
+    assert_eq!(info.vault_balance, Some(TOKEN_AMOUNT));
+    assert_eq!(info.remaining_amount, TOKEN_AMOUNT);
+
+    // An account not owned by a token program is not read as the vault token account
+    let mut ix = client::get_escrow_info_ix(&env.program_id, &keys);
+    ix.accounts[2].pubkey = keys.buyer;
+    let result = env.process(&[ix], &[]).await;
+    assert_instruction_error(result, InstructionError::IncorrectProgramId);
+}
+
+#[tokio::test]
+async fn test_seller_bond_in_tokens() {
+    let mut env = TestEnv::start().await;
+    let mint = env.create_mint().await;
+    let bond = TOKEN_AMOUNT / 2;
+    let keys = env.open_bonded_escrow(mint, SPL_TOKEN_PROGRAM_ID, TOKEN_AMOUNT, bond).await;
+    let buyer = env.buyer.insecure_clone();
+    let seller = env.seller.insecure_clone();
+    let vault_token = keys.token_account(&keys.vault(&env.program_id));
+    let seller_token = keys.token_account(&keys.seller);
+    let seller_tokens = env.token_balance(&seller_token).await;
+
+    // The seller's deposit creates the vault token account and pays its rent
+    let seller_lamports = env.balance(&keys.seller).await;
+    env.process(&[client::fund_collateral_ix(&env.program_id, &keys)], &[&seller]).await.unwrap();
+    let vault_token_rent = env.balance(&vault_token).await;
+    assert_eq!(env.balance(&keys.seller).await, seller_lamports - vault_token_rent);
+    assert_eq!(env.token_balance(&vault_token).await, bond);
+    assert_eq!(env.token_balance(&seller_token).await, seller_tokens - bond);
+
+    env.process(&[client::fund_escrow_ix(&env.program_id, &keys)], &[&buyer]).await.unwrap();
+    assert_eq!(env.token_balance(&vault_token).await, TOKEN_AMOUNT + bond);
+    env.process(&[client::seller_confirm_ix(&env.program_id, &keys)], &[&seller]).await.unwrap();
+    env.process(&[client::confirm_escrow_ix(&env.program_id, &keys)], &[&buyer]).await.unwrap();
+    assert_eq!(env.token_balance(&vault_token).await, 0);
+    assert_eq!(env.token_balance(&seller_token).await, seller_tokens + TOKEN_AMOUNT);
+
+    // All rent, the vault token account's included, goes to the recorded rent payer
+    let buyer_lamports = env.balance(&keys.buyer).await;
+    let seller_lamports = env.balance(&keys.seller).await;
+    let reclaimed = env.balance(&keys.escrow).await + env.balance(&keys.vault(&env.program_id)).await;
+    env.process(&[client::close_escrow_ix(&env.program_id, &keys)], &[]).await.unwrap();
+    assert!(!env.account_exists(&vault_token).await);
+    assert_eq!(env.balance(&keys.rent_payer).await, buyer_lamports + reclaimed + vault_token_rent);
+    assert_eq!(env.balance(&keys.seller).await, seller_lamports);
+}